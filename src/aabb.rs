@@ -1,5 +1,6 @@
 use crate::{ray::Ray, vec_three::Vec3};
 
+#[derive(Debug, Clone, Copy)]
 pub struct AABB {
     pub minimum: Vec3,
     pub maximum: Vec3,
@@ -42,4 +43,31 @@ impl AABB {
 
         AABB { minimum, maximum }
     }
+
+    pub fn from_point(point: &Vec3) -> AABB {
+        AABB {
+            minimum: *point,
+            maximum: *point,
+        }
+    }
+
+    pub fn centroid(&self) -> Vec3 {
+        (self.minimum + self.maximum) * 0.5
+    }
+
+    pub fn surface_area(&self) -> f32 {
+        let extent = self.maximum - self.minimum;
+        2.0 * (extent.x * extent.y + extent.y * extent.z + extent.z * extent.x)
+    }
+
+    pub fn longest_axis(&self) -> u8 {
+        let extent = self.maximum - self.minimum;
+        if extent.x > extent.y && extent.x > extent.z {
+            0
+        } else if extent.y > extent.z {
+            1
+        } else {
+            2
+        }
+    }
 }
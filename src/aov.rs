@@ -0,0 +1,191 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::vec_three::Vec3;
+
+/// Arbitrary output variable: a buffer of what the camera rays hit first, saved alongside the
+/// image for compositing and denoising.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Aov {
+    /// Color of the surface, without any lighting.
+    Albedo,
+    /// Shading normal in world space, facing the camera.
+    Normal,
+    /// Hit point in world space.
+    Position,
+    /// Distance along the camera ray, `HitRecord::t`.
+    Depth,
+    /// Index of the object in `Scene::objects`, starting at 1.
+    ObjectId,
+    /// Id of the material given by the scene, starting at 1, see `Scene::material_id`.
+    MaterialId,
+}
+
+impl fmt::Display for Aov {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Aov::Albedo => "albedo",
+            Aov::Normal => "normal",
+            Aov::Position => "position",
+            Aov::Depth => "depth",
+            Aov::ObjectId => "object-id",
+            Aov::MaterialId => "material-id",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for Aov {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_lowercase().as_str() {
+            "albedo" => Ok(Aov::Albedo),
+            "normal" => Ok(Aov::Normal),
+            "position" => Ok(Aov::Position),
+            "depth" => Ok(Aov::Depth),
+            "object-id" => Ok(Aov::ObjectId),
+            "material-id" => Ok(Aov::MaterialId),
+            _ => Err(format!(
+                "unknown AOV '{}', expected one of albedo, normal, position, depth, object-id, \
+                 material-id",
+                value
+            )),
+        }
+    }
+}
+
+/// Output variables of one pixel.
+///
+/// Albedo and normal are averaged over all the samples of the pixel, the ones that missed
+/// everything counting as zero, so they fade out along the silhouettes. Position and depth are
+/// averaged over the samples that hit something, and are zero and infinite when none did. The
+/// ids are the ones of the first sample that hit something, 0 when none did.
+#[derive(Debug, Clone, Copy)]
+pub struct Aovs {
+    pub albedo: Vec3,
+    pub normal: Vec3,
+    pub position: Vec3,
+    pub depth: f32,
+    pub object_id: u32,
+    pub material_id: u32,
+}
+
+impl Aovs {
+    /// Value of `aov` as a color, scalars being repeated over the three channels.
+    pub fn value(&self, aov: Aov) -> Vec3 {
+        let scalar = |value: f32| Vec3::new(value, value, value);
+        match aov {
+            Aov::Albedo => self.albedo,
+            Aov::Normal => self.normal,
+            Aov::Position => self.position,
+            Aov::Depth => scalar(self.depth),
+            Aov::ObjectId => scalar(self.object_id as f32),
+            Aov::MaterialId => scalar(self.material_id as f32),
+        }
+    }
+}
+
+/// What the camera ray of one sample hit first.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct AovSample {
+    pub albedo: Vec3,
+    pub normal: Vec3,
+    pub position: Vec3,
+    pub depth: f32,
+    pub object_id: u32,
+    pub material_id: u32,
+}
+
+/// Sums of the output variables of the samples of a pixel.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct AovPixel {
+    samples: u32,
+    hits: u32,
+    albedo: Vec3,
+    normal: Vec3,
+    position: Vec3,
+    depth: f32,
+    object_id: u32,
+    material_id: u32,
+}
+
+impl AovPixel {
+    pub fn new() -> AovPixel {
+        AovPixel {
+            samples: 0,
+            hits: 0,
+            albedo: Vec3::zero(),
+            normal: Vec3::zero(),
+            position: Vec3::zero(),
+            depth: 0.0,
+            object_id: 0,
+            material_id: 0,
+        }
+    }
+
+    /// Adds a sample, `None` when its camera ray missed everything.
+    pub fn add(&mut self, sample: Option<AovSample>) {
+        self.samples += 1;
+        let sample = match sample {
+            Some(sample) => sample,
+            None => return,
+        };
+
+        // the ids can't be averaged, the first sample that hits something gives them
+        if self.hits == 0 {
+            self.object_id = sample.object_id;
+            self.material_id = sample.material_id;
+        }
+        self.hits += 1;
+        self.albedo = self.albedo + sample.albedo;
+        self.normal = self.normal + sample.normal;
+        self.position = self.position + sample.position;
+        self.depth += sample.depth;
+    }
+
+    pub fn aovs(&self) -> Aovs {
+        let samples = self.samples.max(1) as f32;
+        let (position, depth) = match self.hits {
+            0 => (Vec3::zero(), f32::INFINITY),
+            hits => (self.position / hits as f32, self.depth / hits as f32),
+        };
+
+        Aovs {
+            albedo: self.albedo / samples,
+            normal: self.normal / samples,
+            position,
+            depth,
+            object_id: self.object_id,
+            material_id: self.material_id,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(object_id: u32, material_id: u32) -> AovSample {
+        AovSample {
+            albedo: Vec3::new(1.0, 1.0, 1.0),
+            normal: Vec3::new(0.0, 0.0, 1.0),
+            position: Vec3::zero(),
+            depth: 2.0,
+            object_id,
+            material_id,
+        }
+    }
+
+    #[test]
+    fn takes_the_ids_of_the_first_sample_that_hits() {
+        let mut pixel = AovPixel::new();
+        pixel.add(None);
+        pixel.add(Some(sample(3, 4)));
+        pixel.add(Some(sample(5, 6)));
+
+        let aovs = pixel.aovs();
+        assert_eq!((aovs.object_id, aovs.material_id), (3, 4));
+        assert_eq!(aovs.depth, 2.0);
+    }
+}
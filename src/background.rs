@@ -0,0 +1,54 @@
+use std::sync::Arc;
+
+use crate::{environment::EnvironmentMap, ray::Ray, vec_three::Vec3};
+
+const SKY_BOTTOM_COLOR: Vec3 = Vec3 {
+    x: 1.0,
+    y: 1.0,
+    z: 1.0,
+};
+const SKY_TOP_COLOR: Vec3 = Vec3 {
+    x: 0.5,
+    y: 0.7,
+    z: 1.0,
+};
+
+/// Radiance of the rays that don't hit any object in the scene.
+#[derive(Clone)]
+pub enum Background {
+    Color(Vec3),
+    /// Blend between `bottom` and `top` based on how much the ray points along `up`.
+    Gradient {
+        bottom: Vec3,
+        top: Vec3,
+        up: Vec3,
+    },
+    Environment(Arc<EnvironmentMap>),
+    Custom(Arc<dyn Fn(&Ray) -> Vec3 + Send + Sync>),
+}
+
+impl Default for Background {
+    fn default() -> Self {
+        Background::Gradient {
+            bottom: SKY_BOTTOM_COLOR,
+            top: SKY_TOP_COLOR,
+            up: Vec3::new(0.0, 1.0, 0.0),
+        }
+    }
+}
+
+impl Background {
+    pub fn color(&self, ray: &Ray) -> Vec3 {
+        match self {
+            Background::Color(color) => *color,
+            Background::Gradient { bottom, top, up } => {
+                let unit_direction = ray.direction.unit_vector();
+                // normalize the t to be a value between 0 and 1
+                let t = 0.5 * (Vec3::dot(&unit_direction, &up.unit_vector()) + 1.0);
+                *bottom * (1.0 - t) + *top * t
+            }
+            Background::Environment(environment) => environment.color(&ray.direction),
+            Background::Custom(background) => background(ray),
+        }
+    }
+}
@@ -0,0 +1,497 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::{aabb::AABB, hit_record::HitRecord, ray::Ray, vec_three::Vec3};
+
+const MAX_LEAF_SIZE: usize = 4;
+const MAX_TRAVERSAL_DEPTH: usize = 64;
+// leaves are forced past this depth so that the traversal stack never overflows
+const MAX_BUILD_DEPTH: usize = MAX_TRAVERSAL_DEPTH - 2;
+const SAH_BINS: usize = 16;
+// relative cost of visiting an interior node compared to intersecting a primitive
+const TRAVERSAL_COST: f32 = 0.125;
+const INTERSECTION_COST: f32 = 1.0;
+
+/// How the primitives of a node are divided between its two children.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SplitStrategy {
+    /// Split at the middle of the centroid bounds along the longest axis.
+    Midpoint,
+    /// Split into two halves with the same number of primitives along the longest axis.
+    EqualCount,
+    /// Binned surface area heuristic over all three axes.
+    #[default]
+    SAH,
+}
+
+impl fmt::Display for SplitStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            SplitStrategy::Midpoint => "midpoint",
+            SplitStrategy::EqualCount => "equal-count",
+            SplitStrategy::SAH => "sah",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for SplitStrategy {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_lowercase().as_str() {
+            "midpoint" => Ok(SplitStrategy::Midpoint),
+            "equal-count" => Ok(SplitStrategy::EqualCount),
+            "sah" => Ok(SplitStrategy::SAH),
+            _ => Err(format!(
+                "unknown split strategy '{}', expected one of sah, midpoint, equal-count",
+                value
+            )),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum BVHNode {
+    Interior {
+        bounding_box: AABB,
+        left: usize,
+        right: usize,
+        axis: u8,
+    },
+    Leaf {
+        bounding_box: AABB,
+        start: usize,
+        count: usize,
+    },
+}
+
+impl BVHNode {
+    fn bounding_box(&self) -> &AABB {
+        match self {
+            BVHNode::Interior { bounding_box, .. } => bounding_box,
+            BVHNode::Leaf { bounding_box, .. } => bounding_box,
+        }
+    }
+}
+
+/// Statistics describing the shape of a built `BVH`.
+#[derive(Debug, Clone, Copy)]
+pub struct BVHStats {
+    pub node_count: usize,
+    pub leaf_count: usize,
+    pub max_depth: usize,
+    pub min_leaf_size: usize,
+    pub max_leaf_size: usize,
+    pub average_leaf_size: f32,
+    pub unbounded_count: usize,
+    /// Expected cost of tracing a ray through the tree according to the surface area heuristic.
+    pub sah_cost: f32,
+}
+
+impl fmt::Display for BVHStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} nodes, {} leaves, depth {}, leaf size {}..={} (avg {:.2}), {} unbounded, SAH cost {:.2}",
+            self.node_count,
+            self.leaf_count,
+            self.max_depth,
+            self.min_leaf_size,
+            self.max_leaf_size,
+            self.average_leaf_size,
+            self.unbounded_count,
+            self.sah_cost
+        )
+    }
+}
+
+/// Bounding volume hierarchy over a list of primitives.
+///
+/// The hierarchy only stores indices into the primitive list it was built from, so the
+/// same structure is used for scene objects as well as for anything else that can
+/// provide bounding boxes. Primitives without a bounding box are tested on every ray.
+#[derive(Clone)]
+pub struct BVH {
+    nodes: Vec<BVHNode>,
+    indices: Vec<usize>,
+    unbounded: Vec<usize>,
+}
+
+impl BVH {
+    pub fn new(bounding_boxes: &[Option<AABB>], strategy: SplitStrategy) -> Self {
+        let mut indices = vec![];
+        let mut unbounded = vec![];
+        for (index, bounding_box) in bounding_boxes.iter().enumerate() {
+            match bounding_box {
+                Some(_) => indices.push(index),
+                None => unbounded.push(index),
+            }
+        }
+
+        let boxes: Vec<AABB> = bounding_boxes
+            .iter()
+            .map(|bounding_box| bounding_box.unwrap_or_else(|| AABB::from_point(&Vec3::zero())))
+            .collect();
+        let centroids: Vec<_> = boxes.iter().map(|b| b.centroid()).collect();
+
+        let mut bvh = BVH {
+            nodes: Vec::with_capacity(2 * indices.len()),
+            indices,
+            unbounded,
+        };
+        if !bvh.indices.is_empty() {
+            let count = bvh.indices.len();
+            bvh.build(&boxes, &centroids, strategy, 0, count, 0);
+        }
+        bvh
+    }
+
+    fn build(
+        &mut self,
+        boxes: &[AABB],
+        centroids: &[Vec3],
+        strategy: SplitStrategy,
+        start: usize,
+        end: usize,
+        depth: usize,
+    ) -> usize {
+        let mut bounding_box = boxes[self.indices[start]];
+        let mut centroid_box = AABB::from_point(&centroids[self.indices[start]]);
+        for &index in self.indices[start + 1..end].iter() {
+            bounding_box = AABB::surrounding_box(&bounding_box, &boxes[index]);
+            centroid_box =
+                AABB::surrounding_box(&centroid_box, &AABB::from_point(&centroids[index]));
+        }
+
+        let node_index = self.nodes.len();
+        let count = end - start;
+        let axis = centroid_box.longest_axis();
+        let extent = centroid_box.maximum.get(axis) - centroid_box.minimum.get(axis);
+
+        // all the centroids are at the same point, so splitting any further doesn't help
+        let split = match count <= 1 || extent <= 0.0 || depth >= MAX_BUILD_DEPTH {
+            true => None,
+            false => match strategy {
+                SplitStrategy::Midpoint => {
+                    self.split_midpoint(centroids, &centroid_box, start, end)
+                }
+                SplitStrategy::EqualCount => match count <= MAX_LEAF_SIZE {
+                    true => None,
+                    false => Some(self.split_equal_count(centroids, axis, start, end)),
+                },
+                SplitStrategy::SAH => {
+                    self.split_sah(boxes, centroids, &bounding_box, &centroid_box, start, end)
+                }
+            },
+        };
+
+        let (mid, axis) = match split {
+            Some(split) => split,
+            None => {
+                self.nodes.push(BVHNode::Leaf {
+                    bounding_box,
+                    start,
+                    count,
+                });
+                return node_index;
+            }
+        };
+
+        // the children are filled in once they are built
+        self.nodes.push(BVHNode::Leaf {
+            bounding_box,
+            start,
+            count: 0,
+        });
+        let left = self.build(boxes, centroids, strategy, start, mid, depth + 1);
+        let right = self.build(boxes, centroids, strategy, mid, end, depth + 1);
+        self.nodes[node_index] = BVHNode::Interior {
+            bounding_box,
+            left,
+            right,
+            axis,
+        };
+        node_index
+    }
+
+    fn split_midpoint(
+        &mut self,
+        centroids: &[Vec3],
+        centroid_box: &AABB,
+        start: usize,
+        end: usize,
+    ) -> Option<(usize, u8)> {
+        if end - start <= MAX_LEAF_SIZE {
+            return None;
+        }
+
+        let axis = centroid_box.longest_axis();
+        let midpoint = centroid_box.centroid().get(axis);
+        let mid = start
+            + partition(&mut self.indices[start..end], |index| {
+                centroids[index].get(axis) < midpoint
+            });
+
+        // fall back to an equal count split when all the primitives land on one side
+        match mid == start || mid == end {
+            true => Some(self.split_equal_count(centroids, axis, start, end)),
+            false => Some((mid, axis)),
+        }
+    }
+
+    fn split_equal_count(
+        &mut self,
+        centroids: &[Vec3],
+        axis: u8,
+        start: usize,
+        end: usize,
+    ) -> (usize, u8) {
+        let half = (end - start) / 2;
+        self.indices[start..end].select_nth_unstable_by(half, |&a, &b| {
+            centroids[a]
+                .get(axis)
+                .partial_cmp(&centroids[b].get(axis))
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+        (start + half, axis)
+    }
+
+    fn split_sah(
+        &mut self,
+        boxes: &[AABB],
+        centroids: &[Vec3],
+        bounding_box: &AABB,
+        centroid_box: &AABB,
+        start: usize,
+        end: usize,
+    ) -> Option<(usize, u8)> {
+        let count = end - start;
+        let bin_index = |centroid: &Vec3, axis: u8| {
+            let min = centroid_box.minimum.get(axis);
+            let extent = centroid_box.maximum.get(axis) - min;
+            let bin = ((centroid.get(axis) - min) / extent * SAH_BINS as f32) as usize;
+            bin.min(SAH_BINS - 1)
+        };
+
+        let mut best: Option<(f32, u8, usize)> = None;
+        for axis in 0..3u8 {
+            let extent = centroid_box.maximum.get(axis) - centroid_box.minimum.get(axis);
+            if extent <= 0.0 {
+                continue;
+            }
+
+            let mut bin_boxes: [Option<AABB>; SAH_BINS] = [None; SAH_BINS];
+            let mut bin_counts = [0usize; SAH_BINS];
+            for &index in self.indices[start..end].iter() {
+                let bin = bin_index(&centroids[index], axis);
+                bin_counts[bin] += 1;
+                bin_boxes[bin] = Some(surrounding_option(&bin_boxes[bin], &boxes[index]));
+            }
+
+            // sweep from the right to get the area and count of every right hand side
+            let mut right_areas = [0.0; SAH_BINS];
+            let mut right_counts = [0usize; SAH_BINS];
+            let mut right_box: Option<AABB> = None;
+            let mut right_count = 0;
+            for bin in (1..SAH_BINS).rev() {
+                if let Some(bin_box) = &bin_boxes[bin] {
+                    right_box = Some(surrounding_option(&right_box, bin_box));
+                }
+                right_count += bin_counts[bin];
+                right_areas[bin] = right_box.map_or(0.0, |b| b.surface_area());
+                right_counts[bin] = right_count;
+            }
+
+            let mut left_box: Option<AABB> = None;
+            let mut left_count = 0;
+            for split in 1..SAH_BINS {
+                if let Some(bin_box) = &bin_boxes[split - 1] {
+                    left_box = Some(surrounding_option(&left_box, bin_box));
+                }
+                left_count += bin_counts[split - 1];
+                if left_count == 0 || right_counts[split] == 0 {
+                    continue;
+                }
+
+                let left_area = left_box.map_or(0.0, |b| b.surface_area());
+                let cost =
+                    left_area * left_count as f32 + right_areas[split] * right_counts[split] as f32;
+                if best.is_none_or(|(best_cost, _, _)| cost < best_cost) {
+                    best = Some((cost, axis, split));
+                }
+            }
+        }
+
+        let (cost, axis, split) = match best {
+            Some(best) => best,
+            None => {
+                // the binning couldn't separate the primitives, e.g. when every centroid falls
+                // in the same bin, so divide them by count instead
+                return match count <= MAX_LEAF_SIZE {
+                    true => None,
+                    false => Some(self.split_equal_count(
+                        centroids,
+                        centroid_box.longest_axis(),
+                        start,
+                        end,
+                    )),
+                };
+            }
+        };
+
+        let area = bounding_box.surface_area();
+        let split_cost = match area > 0.0 {
+            true => TRAVERSAL_COST + INTERSECTION_COST * cost / area,
+            false => TRAVERSAL_COST + INTERSECTION_COST * count as f32,
+        };
+        let leaf_cost = INTERSECTION_COST * count as f32;
+        if count <= MAX_LEAF_SIZE && leaf_cost <= split_cost {
+            return None;
+        }
+
+        let mid = start
+            + partition(&mut self.indices[start..end], |index| {
+                bin_index(&centroids[index], axis) < split
+            });
+        Some((mid, axis))
+    }
+
+    pub fn hit<T, F>(
+        &self,
+        ray: &Ray,
+        t_min: f32,
+        t_max: f32,
+        mut hit_primitive: F,
+    ) -> Option<(HitRecord, T)>
+    where
+        F: FnMut(usize, f32, f32) -> Option<(HitRecord, T)>,
+    {
+        let mut hit_record: Option<(HitRecord, T)> = None;
+        let mut closest_so_far = t_max;
+
+        for &index in self.unbounded.iter() {
+            if let Some(temp_hit_record) = hit_primitive(index, t_min, closest_so_far) {
+                closest_so_far = temp_hit_record.0.t;
+                hit_record = Some(temp_hit_record);
+            }
+        }
+
+        if self.nodes.is_empty() {
+            return hit_record;
+        }
+
+        let mut stack = [0usize; MAX_TRAVERSAL_DEPTH];
+        let mut stack_size = 1;
+        while stack_size > 0 {
+            stack_size -= 1;
+            let node = &self.nodes[stack[stack_size]];
+            if !node.bounding_box().hit(ray, t_min, closest_so_far) {
+                continue;
+            }
+
+            match *node {
+                BVHNode::Leaf { start, count, .. } => {
+                    for &index in self.indices[start..start + count].iter() {
+                        if let Some(temp_hit_record) = hit_primitive(index, t_min, closest_so_far) {
+                            closest_so_far = temp_hit_record.0.t;
+                            hit_record = Some(temp_hit_record);
+                        }
+                    }
+                }
+                BVHNode::Interior {
+                    left, right, axis, ..
+                } => {
+                    // visit the child closer to the ray origin first so that farther nodes can
+                    // be culled by the closest hit found so far
+                    let (near, far) = match ray.direction.get(axis) < 0.0 {
+                        true => (right, left),
+                        false => (left, right),
+                    };
+                    stack[stack_size] = far;
+                    stack[stack_size + 1] = near;
+                    stack_size += 2;
+                }
+            }
+        }
+
+        hit_record
+    }
+
+    pub fn bounding_box(&self) -> Option<AABB> {
+        if !self.unbounded.is_empty() {
+            return None;
+        }
+        self.nodes.first().map(|node| *node.bounding_box())
+    }
+
+    pub fn stats(&self) -> BVHStats {
+        let mut stats = BVHStats {
+            node_count: self.nodes.len(),
+            leaf_count: 0,
+            max_depth: 0,
+            min_leaf_size: 0,
+            max_leaf_size: 0,
+            average_leaf_size: 0.0,
+            unbounded_count: self.unbounded.len(),
+            sah_cost: INTERSECTION_COST * self.unbounded.len() as f32,
+        };
+        if self.nodes.is_empty() {
+            return stats;
+        }
+
+        let root_area = self.nodes[0].bounding_box().surface_area();
+        let mut min_leaf_size = usize::MAX;
+        let mut total_leaf_size = 0;
+        let mut stack = vec![(0, 0)];
+        while let Some((node_index, depth)) = stack.pop() {
+            let node = &self.nodes[node_index];
+            // an infinitely thin root would make every probability zero, so count every node
+            let probability = match root_area > 0.0 {
+                true => node.bounding_box().surface_area() / root_area,
+                false => 1.0,
+            };
+            stats.max_depth = stats.max_depth.max(depth);
+            match *node {
+                BVHNode::Leaf { count, .. } => {
+                    stats.leaf_count += 1;
+                    min_leaf_size = min_leaf_size.min(count);
+                    stats.max_leaf_size = stats.max_leaf_size.max(count);
+                    total_leaf_size += count;
+                    stats.sah_cost += probability * INTERSECTION_COST * count as f32;
+                }
+                BVHNode::Interior { left, right, .. } => {
+                    stats.sah_cost += probability * TRAVERSAL_COST;
+                    stack.push((left, depth + 1));
+                    stack.push((right, depth + 1));
+                }
+            }
+        }
+        stats.min_leaf_size = min_leaf_size;
+        stats.average_leaf_size = total_leaf_size as f32 / stats.leaf_count as f32;
+
+        stats
+    }
+}
+
+fn surrounding_option(bounding_box: &Option<AABB>, other: &AABB) -> AABB {
+    match bounding_box {
+        Some(bounding_box) => AABB::surrounding_box(bounding_box, other),
+        None => *other,
+    }
+}
+
+/// Moves the indices matching `predicate` to the front and returns how many there are.
+fn partition<F>(indices: &mut [usize], predicate: F) -> usize
+where
+    F: Fn(usize) -> bool,
+{
+    let mut mid = 0;
+    for i in 0..indices.len() {
+        if predicate(indices[i]) {
+            indices.swap(i, mid);
+            mid += 1;
+        }
+    }
+    mid
+}
@@ -1,5 +1,31 @@
 use crate::{ray::Ray, vec_three::Vec3};
 
+/// Parameters of a camera that don't depend on the image size.
+#[derive(Debug, Clone, Copy)]
+pub struct CameraSettings {
+    pub look_from: Vec3,
+    pub look_at: Vec3,
+    pub vup: Vec3,
+    /// Vertical field of view in degrees.
+    pub vertical_field_of_view: f32,
+    pub aperture: f32,
+    pub focus_dist: f32,
+}
+
+impl CameraSettings {
+    pub fn camera(&self, aspect_ratio: f32) -> Camera {
+        Camera::new(
+            aspect_ratio,
+            self.vertical_field_of_view,
+            &self.look_from,
+            &self.look_at,
+            &self.vup,
+            self.aperture,
+            self.focus_dist,
+        )
+    }
+}
+
 #[derive(Clone, Copy)]
 pub struct Camera {
     origin: Vec3,
@@ -43,8 +69,10 @@ impl Camera {
         }
     }
 
-    pub fn get_origin_ray(&self, u: f32, v: f32) -> Ray {
-        let rand_direction = Vec3::random_in_unit_disk() * self.lens_radius;
+    /// Ray through (`u`, `v`) of the viewport from the point of the lens picked by the uniform
+    /// `lens_sample` in [0, 1)².
+    pub fn get_origin_ray(&self, u: f32, v: f32, lens_sample: (f32, f32)) -> Ray {
+        let rand_direction = Vec3::sample_in_unit_disk(lens_sample) * self.lens_radius;
         let offset = u * rand_direction.x + v * rand_direction.y;
         // as the camera is present in the origin, the direction vector would the position of the point
         let direction =
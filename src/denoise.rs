@@ -0,0 +1,199 @@
+use std::thread;
+
+use crate::{aov::Aovs, vec_three::Vec3};
+
+// passes of the filter, each one skipping twice as many pixels between its taps as the last,
+// so that five of them reach 2 * (1 + 2 + 4 + 8 + 16) = 62 pixels away
+const ITERATIONS: u32 = 5;
+// B3 spline, the weights of the taps along x and y
+const KERNEL: [f32; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+// how many standard errors apart two brightnesses can be and still be averaged
+const SIGMA_LUMINANCE: f32 = 4.0;
+// exponent of the cosine between two normals
+const SIGMA_NORMAL: i32 = 128;
+const SIGMA_ALBEDO: f32 = 0.1;
+const EPSILON: f32 = 1e-6;
+
+/// Averages the colors of the pixels with the ones of their neighbours that look like the same
+/// surface, with an edge-avoiding à-trous wavelet filter.
+///
+/// Two pixels are averaged when their albedo and normal are close and their brightnesses are
+/// within a few standard errors of each other, so edges, textures and the features the noise
+/// can't hide are kept. `variances` is the variance of the mean brightness of every pixel, it
+/// is filtered along with the colors from one pass to the next as in SVGF (Schied et al.,
+/// 2017). Everything is stored row by row from the top left.
+///
+/// The rows are split between `threads` threads.
+///
+/// # Panics
+///
+/// The `denoise` function will panic if the number of pixels doesn't match the size.
+pub fn denoise(
+    width: u32,
+    height: u32,
+    colors: &[Vec3],
+    variances: &[f32],
+    aovs: &[Aovs],
+    threads: usize,
+) -> Vec<Vec3> {
+    let size = width as usize * height as usize;
+    assert_eq!(colors.len(), size);
+    assert_eq!(variances.len(), size);
+    assert_eq!(aovs.len(), size);
+
+    let (width, height) = (width as usize, height as usize);
+    // the normals are averaged over the pixels, so shorter along the silhouettes
+    let normals: Vec<Vec3> = aovs
+        .iter()
+        .map(|aovs| match aovs.normal.length() > EPSILON {
+            true => aovs.normal.unit_vector(),
+            false => Vec3::zero(),
+        })
+        .collect();
+    let rows_per_thread = height.div_ceil(threads.max(1)).max(1);
+
+    let mut colors = colors.to_vec();
+    let mut variances = variances.to_vec();
+    for iteration in 0..ITERATIONS {
+        let step = 1 << iteration;
+        let blurred_variances = blur(width, height, &variances);
+        let mut filtered_colors = vec![Vec3::zero(); size];
+        let mut filtered_variances = vec![0.0; size];
+
+        let filter_pixel = |x: usize, y: usize| {
+            let index = y * width + x;
+            let luminance = colors[index].luminance();
+
+            let mut color_sum = Vec3::zero();
+            let mut variance_sum = 0.0;
+            let mut weights = 0.0;
+            for (tap_y, kernel_y) in KERNEL.iter().enumerate() {
+                let other_y = y as isize + (tap_y as isize - 2) * step;
+                if !(0..height as isize).contains(&other_y) {
+                    continue;
+                }
+                for (tap_x, kernel_x) in KERNEL.iter().enumerate() {
+                    let other_x = x as isize + (tap_x as isize - 2) * step;
+                    if !(0..width as isize).contains(&other_x) {
+                        continue;
+                    }
+                    let other = other_y as usize * width + other_x as usize;
+
+                    // the variance of both pixels counts, as a pixel whose few samples all
+                    // happened to agree would otherwise stay apart
+                    let sigma = SIGMA_LUMINANCE
+                        * (blurred_variances[index] + blurred_variances[other]).sqrt()
+                        + EPSILON;
+                    let luminance_weight =
+                        (-(luminance - colors[other].luminance()).abs() / sigma).exp();
+                    let albedo_difference = aovs[index].albedo - aovs[other].albedo;
+                    let albedo_weight =
+                        (-albedo_difference.length_squared() / (SIGMA_ALBEDO * SIGMA_ALBEDO)).exp();
+                    let weight = kernel_x
+                        * kernel_y
+                        * luminance_weight
+                        * albedo_weight
+                        * normal_weight(&normals[index], &normals[other]);
+
+                    color_sum = color_sum + colors[other] * weight;
+                    variance_sum += variances[other] * weight * weight;
+                    weights += weight;
+                }
+            }
+
+            // the pixel itself always has a weight
+            (color_sum / weights, variance_sum / (weights * weights))
+        };
+
+        thread::scope(|scope| {
+            let bands = filtered_colors
+                .chunks_mut(rows_per_thread * width)
+                .zip(filtered_variances.chunks_mut(rows_per_thread * width));
+            for (band, (band_colors, band_variances)) in bands.enumerate() {
+                let filter_pixel = &filter_pixel;
+                scope.spawn(move || {
+                    let pixels = band_colors.iter_mut().zip(band_variances.iter_mut());
+                    for (offset, (color, variance)) in pixels.enumerate() {
+                        let index = band * rows_per_thread * width + offset;
+                        (*color, *variance) = filter_pixel(index % width, index / width);
+                    }
+                });
+            }
+        });
+
+        colors = filtered_colors;
+        variances = filtered_variances;
+    }
+
+    colors
+}
+
+// pixels that hit nothing have no normal, they are only averaged with each other
+fn normal_weight(normal: &Vec3, other_normal: &Vec3) -> f32 {
+    match (
+        normal.length_squared() > 0.0,
+        other_normal.length_squared() > 0.0,
+    ) {
+        (true, true) => Vec3::dot(normal, other_normal).max(0.0).powi(SIGMA_NORMAL),
+        (false, false) => 1.0,
+        _ => 0.0,
+    }
+}
+
+// 3x3 Gaussian, which makes the variance of a single pixel more reliable
+fn blur(width: usize, height: usize, values: &[f32]) -> Vec<f32> {
+    let kernel = [0.25, 0.5, 0.25];
+    let mut blurred = Vec::with_capacity(values.len());
+    for y in 0..height {
+        for x in 0..width {
+            let mut sum = 0.0;
+            let mut weights = 0.0;
+            for (other_y, kernel_y) in (y as isize - 1..).zip(kernel) {
+                for (other_x, kernel_x) in (x as isize - 1..).zip(kernel) {
+                    if (0..height as isize).contains(&other_y)
+                        && (0..width as isize).contains(&other_x)
+                    {
+                        let weight = kernel_x * kernel_y;
+                        sum += values[other_y as usize * width + other_x as usize] * weight;
+                        weights += weight;
+                    }
+                }
+            }
+            blurred.push(sum / weights);
+        }
+    }
+    blurred
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn leaves_constant_images_unchanged() {
+        let (width, height) = (37, 23);
+        let size = (width * height) as usize;
+        let color = Vec3::new(0.2, 0.5, 3.0);
+        // features and noise levels that differ from pixel to pixel, with pixels that missed
+        // everything among them
+        let aovs: Vec<Aovs> = (0..size)
+            .map(|index| Aovs {
+                albedo: Vec3::new((index % 3) as f32 * 0.4, 0.5, 0.5),
+                normal: match index % 7 {
+                    0 => Vec3::zero(),
+                    other => Vec3::new(other as f32, 1.0, -2.0).unit_vector(),
+                },
+                position: Vec3::zero(),
+                depth: 1.0,
+                object_id: 1,
+                material_id: 1,
+            })
+            .collect();
+        let variances: Vec<f32> = (0..size).map(|index| (index % 5) as f32 * 0.1).collect();
+
+        let denoised = denoise(width, height, &vec![color; size], &variances, &aovs, 3);
+        for pixel in denoised {
+            assert!((pixel - color).length() < 1e-5, "{:?}", pixel);
+        }
+    }
+}
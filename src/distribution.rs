@@ -0,0 +1,186 @@
+/// Piecewise constant distribution over [0, 1) built from non negative function values.
+#[derive(Clone)]
+pub struct Distribution1D {
+    function: Vec<f32>,
+    cdf: Vec<f32>,
+    integral: f32,
+}
+
+impl Distribution1D {
+    /// # Panics
+    ///
+    /// The `new` function will panic if `function` is empty.
+    pub fn new(function: Vec<f32>) -> Distribution1D {
+        assert!(!function.is_empty());
+
+        let count = function.len();
+        let mut cdf = Vec::with_capacity(count + 1);
+        cdf.push(0.0);
+        for i in 0..count {
+            cdf.push(cdf[i] + function[i].max(0.0) / count as f32);
+        }
+
+        let integral = cdf[count];
+        for (i, value) in cdf.iter_mut().enumerate() {
+            // fall back to a uniform distribution when every value is zero
+            *value = match integral > 0.0 {
+                true => *value / integral,
+                false => i as f32 / count as f32,
+            };
+        }
+
+        Distribution1D {
+            function,
+            cdf,
+            integral,
+        }
+    }
+
+    pub fn count(&self) -> usize {
+        self.function.len()
+    }
+
+    pub fn integral(&self) -> f32 {
+        self.integral
+    }
+
+    /// Maps a uniform sample to a position in [0, 1), returning it along with its density
+    /// and the index of the segment it falls in.
+    pub fn sample_continuous(&self, u: f32) -> (f32, f32, usize) {
+        // index of the last cdf entry that is less than or equal to u
+        let offset = self
+            .cdf
+            .partition_point(|&value| value <= u)
+            .saturating_sub(1)
+            .min(self.count() - 1);
+
+        let width = self.cdf[offset + 1] - self.cdf[offset];
+        let du = match width > 0.0 {
+            true => (u - self.cdf[offset]) / width,
+            false => 0.0,
+        };
+
+        let x = ((offset as f32 + du) / self.count() as f32).min(1.0 - f32::EPSILON);
+        (x, self.pdf_at(offset), offset)
+    }
+
+    pub fn pdf(&self, x: f32) -> f32 {
+        let offset = ((x * self.count() as f32) as usize).min(self.count() - 1);
+        self.pdf_at(offset)
+    }
+
+    fn pdf_at(&self, offset: usize) -> f32 {
+        match self.integral > 0.0 {
+            true => self.function[offset].max(0.0) / self.integral,
+            false => 1.0,
+        }
+    }
+}
+
+/// Piecewise constant distribution over [0, 1)², sampled through the marginal distribution
+/// of the rows and the conditional distribution within the chosen row.
+#[derive(Clone)]
+pub struct Distribution2D {
+    conditional: Vec<Distribution1D>,
+    marginal: Distribution1D,
+}
+
+impl Distribution2D {
+    /// Builds the distribution from `width * height` values stored row by row.
+    ///
+    /// # Panics
+    ///
+    /// The `new` function will panic if the number of values doesn't match the size or if it
+    /// is empty.
+    pub fn new(values: &[f32], width: usize, height: usize) -> Distribution2D {
+        assert!(width > 0 && height > 0);
+        assert_eq!(values.len(), width * height);
+
+        let conditional: Vec<Distribution1D> = values
+            .chunks(width)
+            .map(|row| Distribution1D::new(row.to_vec()))
+            .collect();
+        let marginal = Distribution1D::new(conditional.iter().map(|row| row.integral()).collect());
+
+        Distribution2D {
+            conditional,
+            marginal,
+        }
+    }
+
+    /// Returns the sampled (u, v) position and its density.
+    pub fn sample_continuous(&self, u1: f32, u2: f32) -> ((f32, f32), f32) {
+        let (v, marginal_pdf, row) = self.marginal.sample_continuous(u2);
+        let (u, conditional_pdf, _) = self.conditional[row].sample_continuous(u1);
+        ((u, v), marginal_pdf * conditional_pdf)
+    }
+
+    pub fn pdf(&self, u: f32, v: f32) -> f32 {
+        let row = ((v * self.conditional.len() as f32) as usize).min(self.conditional.len() - 1);
+        self.marginal.pdf(v) * self.conditional[row].pdf(u)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WIDTH: usize = 4;
+    const HEIGHT: usize = 3;
+    const VALUES: [f32; WIDTH * HEIGHT] = [
+        1.0, 2.0, 0.0, 4.0, //
+        0.0, 0.0, 0.0, 0.0, //
+        3.0, 0.5, 8.0, 1.5,
+    ];
+
+    #[test]
+    fn samples_have_the_density_of_their_position() {
+        let distribution = Distribution2D::new(&VALUES, WIDTH, HEIGHT);
+        let steps = 64;
+        for i in 0..steps {
+            for j in 0..steps {
+                let (u1, u2) = (
+                    (i as f32 + 0.5) / steps as f32,
+                    (j as f32 + 0.5) / steps as f32,
+                );
+                let ((u, v), pdf) = distribution.sample_continuous(u1, u2);
+                assert!((0.0..1.0).contains(&u) && (0.0..1.0).contains(&v));
+                assert!(pdf > 0.0, "sampled ({}, {}) where the density is 0", u, v);
+                assert!((pdf - distribution.pdf(u, v)).abs() <= 1e-5 * pdf);
+            }
+        }
+    }
+
+    #[test]
+    fn samples_cells_in_proportion_to_their_values() {
+        let distribution = Distribution2D::new(&VALUES, WIDTH, HEIGHT);
+        let steps = 400;
+        let mut counts = [0; WIDTH * HEIGHT];
+        for i in 0..steps {
+            for j in 0..steps {
+                let (u1, u2) = (
+                    (i as f32 + 0.5) / steps as f32,
+                    (j as f32 + 0.5) / steps as f32,
+                );
+                let ((u, v), _) = distribution.sample_continuous(u1, u2);
+                let cell = (v * HEIGHT as f32) as usize * WIDTH + (u * WIDTH as f32) as usize;
+                counts[cell] += 1;
+            }
+        }
+
+        let total: f32 = VALUES.iter().sum();
+        let cell_area = 1.0 / (WIDTH * HEIGHT) as f32;
+        for (cell, &value) in VALUES.iter().enumerate() {
+            let expected = value / total;
+            let frequency = counts[cell] as f32 / (steps * steps) as f32;
+            assert!((frequency - expected).abs() < 1e-2, "cell {}", cell);
+
+            // the density is constant over the cell and integrates to its probability
+            let (u, v) = (
+                ((cell % WIDTH) as f32 + 0.5) / WIDTH as f32,
+                ((cell / WIDTH) as f32 + 0.5) / HEIGHT as f32,
+            );
+            assert!((distribution.pdf(u, v) * cell_area - expected).abs() < 1e-6);
+        }
+    }
+}
@@ -0,0 +1,235 @@
+use std::f32::consts::PI;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::path::{Path, PathBuf};
+
+use image::codecs::hdr::HdrDecoder;
+use image::ImageError;
+
+use crate::{distribution::Distribution2D, vec_three::Vec3};
+
+#[derive(Debug)]
+pub enum EnvironmentError {
+    Io(io::Error),
+    Image(ImageError),
+    Exr(exr::error::Error),
+    UnsupportedFormat(String),
+}
+
+impl fmt::Display for EnvironmentError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EnvironmentError::Io(error) => write!(f, "{}", error),
+            EnvironmentError::Image(error) => write!(f, "{}", error),
+            EnvironmentError::Exr(error) => write!(f, "{}", error),
+            EnvironmentError::UnsupportedFormat(extension) => write!(
+                f,
+                "unsupported environment map format '{}', expected .hdr or .exr",
+                extension
+            ),
+        }
+    }
+}
+
+impl std::error::Error for EnvironmentError {}
+
+impl From<io::Error> for EnvironmentError {
+    fn from(error: io::Error) -> Self {
+        EnvironmentError::Io(error)
+    }
+}
+
+impl From<ImageError> for EnvironmentError {
+    fn from(error: ImageError) -> Self {
+        EnvironmentError::Image(error)
+    }
+}
+
+impl From<exr::error::Error> for EnvironmentError {
+    fn from(error: exr::error::Error) -> Self {
+        EnvironmentError::Exr(error)
+    }
+}
+
+/// Equirectangular (latitude-longitude) image lighting the scene from infinitely far away.
+///
+/// The top row of the image is straight up along +y and the horizontal center looks down -z.
+#[derive(Clone)]
+pub struct EnvironmentMap {
+    width: usize,
+    height: usize,
+    pixels: Vec<Vec3>,
+    distribution: Distribution2D,
+    path: Option<PathBuf>,
+    /// Rotation around the up axis in degrees.
+    pub rotation: f32,
+    /// Scale applied to every pixel of the image.
+    pub intensity: f32,
+}
+
+impl EnvironmentMap {
+    /// Create a new EnvironmentMap from linear radiance values stored row by row.
+    ///
+    /// # Panics
+    ///
+    /// The `new` function will panic if the image is empty or if the number of pixels doesn't
+    /// match the size.
+    pub fn new(width: usize, height: usize, pixels: Vec<Vec3>) -> EnvironmentMap {
+        assert!(width > 0 && height > 0);
+        assert_eq!(pixels.len(), width * height);
+
+        // rows near the poles cover a smaller solid angle, so they are sampled less often
+        let weights: Vec<f32> = pixels
+            .iter()
+            .enumerate()
+            .map(|(i, pixel)| {
+                let sin_theta = (PI * ((i / width) as f32 + 0.5) / height as f32).sin();
+                pixel.luminance() * sin_theta
+            })
+            .collect();
+        let distribution = Distribution2D::new(&weights, width, height);
+
+        EnvironmentMap {
+            width,
+            height,
+            pixels,
+            distribution,
+            path: None,
+            rotation: 0.0,
+            intensity: 1.0,
+        }
+    }
+
+    /// Loads a Radiance RGBE (`.hdr`) or OpenEXR (`.exr`) image.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<EnvironmentMap, EnvironmentError> {
+        let path = path.as_ref();
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .unwrap_or("")
+            .to_lowercase();
+
+        let mut environment = match extension.as_str() {
+            "hdr" => {
+                let decoder = HdrDecoder::new(BufReader::new(File::open(path)?))?;
+                let metadata = decoder.metadata();
+                let pixels = decoder
+                    .read_image_hdr()?
+                    .iter()
+                    .map(|pixel| Vec3::new(pixel[0], pixel[1], pixel[2]))
+                    .collect();
+                EnvironmentMap::new(metadata.width as usize, metadata.height as usize, pixels)
+            }
+            "exr" => {
+                let image = exr::prelude::read_first_rgba_layer_from_file(
+                    path,
+                    |resolution, _| {
+                        (
+                            resolution.width(),
+                            vec![Vec3::zero(); resolution.width() * resolution.height()],
+                        )
+                    },
+                    |(width, pixels), position, (r, g, b, _): (f32, f32, f32, f32)| {
+                        pixels[position.y() * *width + position.x()] = Vec3::new(r, g, b);
+                    },
+                )?;
+                let size = image.layer_data.size;
+                let (_, pixels) = image.layer_data.channel_data.pixels;
+                EnvironmentMap::new(size.width(), size.height(), pixels)
+            }
+            _ => return Err(EnvironmentError::UnsupportedFormat(extension)),
+        };
+        environment.path = Some(path.to_path_buf());
+        Ok(environment)
+    }
+
+    pub fn with_rotation(mut self, degrees: f32) -> Self {
+        self.rotation = degrees;
+        self
+    }
+
+    pub fn with_intensity(mut self, intensity: f32) -> Self {
+        self.intensity = intensity;
+        self
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// File the map was loaded from, `None` when it was created with `new`.
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Radiance arriving from `direction`, bilinearly filtered.
+    pub fn color(&self, direction: &Vec3) -> Vec3 {
+        let (u, v) = self.direction_to_uv(direction);
+        // pixel centers are at half integer coordinates
+        let x = u * self.width as f32 - 0.5;
+        let y = (v * self.height as f32 - 0.5).clamp(0.0, (self.height - 1) as f32);
+        let x0 = x.floor();
+        let y0 = y.floor();
+        let tx = x - x0;
+        let ty = y - y0;
+
+        let x0 = (x0 as i64).rem_euclid(self.width as i64) as usize;
+        let x1 = (x0 + 1) % self.width;
+        let y0 = y0 as usize;
+        let y1 = (y0 + 1).min(self.height - 1);
+
+        let top = self.pixel(x0, y0) * (1.0 - tx) + self.pixel(x1, y0) * tx;
+        let bottom = self.pixel(x0, y1) * (1.0 - tx) + self.pixel(x1, y1) * tx;
+        (top * (1.0 - ty) + bottom * ty) * self.intensity
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> Vec3 {
+        self.pixels[y * self.width + x]
+    }
+
+    /// Maps a direction to image coordinates between 0 and 1, with v growing downwards.
+    pub fn direction_to_uv(&self, direction: &Vec3) -> (f32, f32) {
+        let unit_direction = direction.unit_vector();
+        let phi = unit_direction.x.atan2(-unit_direction.z) - self.rotation.to_radians();
+        let theta = unit_direction.y.clamp(-1.0, 1.0).acos();
+        let u = (phi / (2.0 * PI) + 0.5).rem_euclid(1.0);
+        (u, theta / PI)
+    }
+
+    /// Inverse of `direction_to_uv`, returns a unit vector.
+    pub fn uv_to_direction(&self, u: f32, v: f32) -> Vec3 {
+        let phi = (u - 0.5) * 2.0 * PI + self.rotation.to_radians();
+        let theta = v * PI;
+        let sin_theta = theta.sin();
+        Vec3::new(sin_theta * phi.sin(), theta.cos(), -sin_theta * phi.cos())
+    }
+
+    /// Picks a direction with a probability proportional to the brightness of the map,
+    /// returning it with its radiance and its density with respect to solid angle.
+    pub fn sample(&self, u1: f32, u2: f32) -> Option<(Vec3, Vec3, f32)> {
+        let ((u, v), uv_pdf) = self.distribution.sample_continuous(u1, u2);
+        let sin_theta = (v * PI).sin();
+        if uv_pdf <= 0.0 || sin_theta <= 0.0 {
+            return None;
+        }
+
+        let direction = self.uv_to_direction(u, v);
+        let pdf = uv_pdf / (2.0 * PI * PI * sin_theta);
+        Some((direction, self.color(&direction), pdf))
+    }
+
+    /// Density with respect to solid angle of `sample` returning `direction`.
+    pub fn pdf(&self, direction: &Vec3) -> f32 {
+        let (u, v) = self.direction_to_uv(direction);
+        let sin_theta = (v * PI).sin();
+        if sin_theta <= 0.0 {
+            return 0.0;
+        }
+        self.distribution.pdf(u, v) / (2.0 * PI * PI * sin_theta)
+    }
+}
@@ -0,0 +1,98 @@
+use crate::{filter::Filter, vec_three::Vec3};
+
+/// Weighted sums of the samples splatted on a rectangle of pixels of an image, in image
+/// coordinates with the origin at the top left.
+#[derive(Clone)]
+pub struct Film {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+    filter: Filter,
+    radius: f32,
+    // sum of the weighted colors and sum of the weights
+    pixels: Vec<(Vec3, f32)>,
+}
+
+impl Film {
+    pub fn new(x: u32, y: u32, width: u32, height: u32, filter: Filter, radius: f32) -> Film {
+        Film {
+            x,
+            y,
+            width,
+            height,
+            filter,
+            radius,
+            pixels: vec![(Vec3::zero(), 0.0); width as usize * height as usize],
+        }
+    }
+
+    /// Number of pixels around a pixel reached by the samples taken inside it.
+    pub fn margin(radius: f32) -> u32 {
+        ((radius + 0.5).ceil() as u32).saturating_sub(1)
+    }
+
+    /// Adds `color` to every pixel of the film whose center is closer than the radius of the
+    /// filter to (`x`, `y`), in pixels from the top left of the image.
+    pub fn add_sample(&mut self, x: f32, y: f32, color: Vec3) {
+        let (start_x, end_x) = self.range(x, self.x, self.width);
+        let (start_y, end_y) = self.range(y, self.y, self.height);
+
+        for pixel_y in start_y..end_y {
+            for pixel_x in start_x..end_x {
+                let weight = self.filter.weight(
+                    x - (pixel_x as f32 + 0.5),
+                    y - (pixel_y as f32 + 0.5),
+                    self.radius,
+                );
+                if weight != 0.0 {
+                    let index = self.index(pixel_x, pixel_y);
+                    let (sum, weights) = &mut self.pixels[index];
+                    *sum = *sum + color * weight;
+                    *weights += weight;
+                }
+            }
+        }
+    }
+
+    /// Adds the samples of `other` where it overlaps this film.
+    pub fn merge(&mut self, other: &Film) {
+        let start_x = self.x.max(other.x);
+        let end_x = (self.x + self.width).min(other.x + other.width);
+        let start_y = self.y.max(other.y);
+        let end_y = (self.y + self.height).min(other.y + other.height);
+
+        for y in start_y..end_y {
+            for x in start_x..end_x {
+                let (other_sum, other_weights) = other.pixels[other.index(x, y)];
+                let index = self.index(x, y);
+                let (sum, weights) = &mut self.pixels[index];
+                *sum = *sum + other_sum;
+                *weights += other_weights;
+            }
+        }
+    }
+
+    /// Weighted average of the samples of the pixel, black when no sample reached it.
+    pub fn color(&self, x: u32, y: u32) -> Vec3 {
+        let (sum, weights) = self.pixels[self.index(x, y)];
+        // filters with negative lobes can leave a pixel with no weight at all
+        match weights > 0.0 {
+            true => sum / weights,
+            false => Vec3::zero(),
+        }
+    }
+
+    // pixels of the film between `start` and `start + length` whose centers are closer than
+    // the radius to `position`
+    fn range(&self, position: f32, start: u32, length: u32) -> (u32, u32) {
+        let first = (position - 0.5 - self.radius).ceil().max(start as f32) as u32;
+        let last = (position - 0.5 + self.radius).floor() + 1.0;
+        (first, (last.max(0.0) as u32).min(start + length))
+    }
+
+    fn index(&self, x: u32, y: u32) -> usize {
+        assert!(x >= self.x && x < self.x + self.width && y >= self.y && y < self.y + self.height);
+        ((y - self.y) * self.width + x - self.x) as usize
+    }
+}
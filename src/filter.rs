@@ -0,0 +1,116 @@
+use std::f32::consts::PI;
+use std::fmt;
+use std::str::FromStr;
+
+/// Reconstruction filter weighting the samples around the center of every pixel, applied
+/// separately along x and y.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Filter {
+    /// Same weight for every sample closer than the radius, a radius of half a pixel only
+    /// keeps the samples of the pixel itself.
+    #[default]
+    Box,
+    /// Weight decreasing linearly to zero at the radius.
+    Tent,
+    /// Gaussian with a standard deviation of a third of the radius, shifted to reach zero at
+    /// the radius.
+    Gaussian,
+    /// Mitchell-Netravali cubic with B = C = 1/3, sharper than the Gaussian.
+    Mitchell,
+    /// Sinc windowed by a wider sinc, the sharpest with some ringing on high contrast edges.
+    Lanczos,
+}
+
+impl Filter {
+    /// Radius in pixels used when the settings don't give one.
+    pub fn default_radius(&self) -> f32 {
+        match self {
+            Filter::Box => 0.5,
+            Filter::Tent => 1.0,
+            Filter::Gaussian => 1.5,
+            Filter::Mitchell => 2.0,
+            Filter::Lanczos => 3.0,
+        }
+    }
+
+    /// Weight of a sample at (`x`, `y`) pixels from the center of a pixel.
+    pub fn weight(&self, x: f32, y: f32, radius: f32) -> f32 {
+        self.weight_1d(x, radius) * self.weight_1d(y, radius)
+    }
+
+    fn weight_1d(&self, x: f32, radius: f32) -> f32 {
+        // half open so that a sample on the border of two pixels only counts for one of them
+        if !(-radius..radius).contains(&x) {
+            return 0.0;
+        }
+        let x = x.abs();
+
+        match self {
+            Filter::Box => 1.0,
+            Filter::Tent => radius - x,
+            Filter::Gaussian => {
+                let sigma = radius / 3.0;
+                let gaussian = |x: f32| (-x * x / (2.0 * sigma * sigma)).exp();
+                gaussian(x) - gaussian(radius)
+            }
+            Filter::Mitchell => {
+                // the cubic is defined over [-2, 2]
+                let x = 2.0 * x / radius;
+                let (b, c) = (1.0 / 3.0, 1.0 / 3.0);
+                let value = match x < 1.0 {
+                    true => {
+                        (12.0 - 9.0 * b - 6.0 * c) * x * x * x
+                            + (-18.0 + 12.0 * b + 6.0 * c) * x * x
+                            + (6.0 - 2.0 * b)
+                    }
+                    false => {
+                        (-b - 6.0 * c) * x * x * x
+                            + (6.0 * b + 30.0 * c) * x * x
+                            + (-12.0 * b - 48.0 * c) * x
+                            + (8.0 * b + 24.0 * c)
+                    }
+                };
+                value / 6.0
+            }
+            Filter::Lanczos => sinc(x) * sinc(x / radius),
+        }
+    }
+}
+
+impl fmt::Display for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Filter::Box => "box",
+            Filter::Tent => "tent",
+            Filter::Gaussian => "gaussian",
+            Filter::Mitchell => "mitchell",
+            Filter::Lanczos => "lanczos",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for Filter {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_lowercase().as_str() {
+            "box" => Ok(Filter::Box),
+            "tent" => Ok(Filter::Tent),
+            "gaussian" => Ok(Filter::Gaussian),
+            "mitchell" => Ok(Filter::Mitchell),
+            "lanczos" => Ok(Filter::Lanczos),
+            _ => Err(format!(
+                "unknown filter '{}', expected one of box, tent, gaussian, mitchell, lanczos",
+                value
+            )),
+        }
+    }
+}
+
+fn sinc(x: f32) -> f32 {
+    match x.abs() < 1e-5 {
+        true => 1.0,
+        false => (PI * x).sin() / (PI * x),
+    }
+}
@@ -1,33 +1,119 @@
-use crate::{aabb::AABB, hit_record::HitRecord, material::Material, ray::Ray, vec_three::Vec3};
+use std::collections::HashMap;
+use std::sync::Arc;
 
-#[derive(Clone, Copy)]
+use crate::{
+    aabb::AABB, bvh::SplitStrategy, hit_record::HitRecord, material::Material, mesh::Mesh,
+    ray::Ray, transform::Transform, vec_three::Vec3,
+};
+
+#[derive(Clone)]
 pub enum Geometry {
     Sphere {
         center: Vec3,
         radius: f32,
         material: Material,
     },
+    Triangle {
+        vertices: [Vec3; 3],
+        normals: Option<[Vec3; 3]>,
+        uvs: Option<[(f32, f32); 3]>,
+        material: Material,
+    },
+    Mesh {
+        mesh: Arc<Mesh>,
+    },
+    /// Places a shared object in the scene with its own transform.
+    Instance {
+        object: Arc<Geometry>,
+        transform: Transform,
+    },
 }
 
+// sine of the angle under which a ray grazing the plane of a triangle misses it, relative so
+// that small triangles and long rays are hit as well as large ones
+const TRIANGLE_EPSILON: f32 = 1e-7;
+// keeps the bounding box of axis aligned triangles from being flat
+const TRIANGLE_BOX_PADDING: f32 = 1e-4;
+
 impl Geometry {
     pub fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<(HitRecord, &Material)> {
         match &self {
-            &Geometry::Sphere {
+            Geometry::Sphere {
                 center,
                 radius,
                 material,
-            } => match Geometry::_hit_sphere(ray, t_min, t_max, &center, *radius) {
-                Some(hit_record) => Some((hit_record, material)),
-                _ => None,
-            },
+            } => Geometry::_hit_sphere(ray, t_min, t_max, center, *radius)
+                .map(|hit_record| (hit_record, material)),
+            Geometry::Triangle {
+                vertices,
+                normals,
+                uvs,
+                material,
+            } => Geometry::hit_triangle(ray, t_min, t_max, vertices, normals, uvs)
+                .map(|hit_record| (hit_record, material)),
+            Geometry::Mesh { mesh } => mesh.hit(ray, t_min, t_max),
+            Geometry::Instance { object, transform } => {
+                Geometry::_hit_instance(ray, t_min, t_max, object, transform)
+            }
+        }
+    }
+
+    /// Every material of the geometry, meshes having several of them.
+    pub fn materials(&self) -> Vec<&Material> {
+        match &self {
+            Geometry::Sphere { material, .. } | Geometry::Triangle { material, .. } => {
+                vec![material]
+            }
+            Geometry::Mesh { mesh } => mesh.materials().iter().collect(),
+            Geometry::Instance { object, .. } => object.materials(),
+        }
+    }
+
+    // rebuilds the hierarchies of the meshes that weren't built with `strategy`, `rebuilt` maps
+    // the address of every mesh already rebuilt to the mesh and its replacement, so that the
+    // meshes shared by several objects stay shared
+    pub(crate) fn rebuild_mesh_bvhs(
+        &mut self,
+        strategy: SplitStrategy,
+        rebuilt: &mut HashMap<usize, (Arc<Mesh>, Arc<Mesh>)>,
+    ) {
+        match self {
+            Geometry::Mesh { mesh } if mesh.split_strategy() != strategy => {
+                let (_, rebuilt_mesh) =
+                    rebuilt
+                        .entry(Arc::as_ptr(mesh) as usize)
+                        .or_insert_with(|| {
+                            let mut rebuilt_mesh = Mesh::clone(mesh);
+                            rebuilt_mesh.build_bvh(strategy);
+                            (Arc::clone(mesh), Arc::new(rebuilt_mesh))
+                        });
+                *mesh = Arc::clone(rebuilt_mesh);
+            }
+            Geometry::Instance { object, .. } if object.has_mesh_bvh_other_than(strategy) => {
+                Arc::make_mut(object).rebuild_mesh_bvhs(strategy, rebuilt);
+            }
+            _ => {}
+        }
+    }
+
+    fn has_mesh_bvh_other_than(&self, strategy: SplitStrategy) -> bool {
+        match self {
+            Geometry::Mesh { mesh } => mesh.split_strategy() != strategy,
+            Geometry::Instance { object, .. } => object.has_mesh_bvh_other_than(strategy),
+            _ => false,
         }
     }
 
     pub fn bounding_box(&self) -> Option<AABB> {
         match &self {
             Geometry::Sphere { center, radius, .. } => {
-                Geometry::_bounding_box_sphere(&center, *radius)
+                Geometry::_bounding_box_sphere(center, *radius)
             }
+            Geometry::Triangle { vertices, .. } => Geometry::bounding_box_triangle(vertices),
+            Geometry::Mesh { mesh } => mesh.bounding_box(),
+            Geometry::Instance { object, transform } => object
+                .bounding_box()
+                .map(|bounding_box| transform.transform_box(&bounding_box)),
         }
     }
 
@@ -60,7 +146,91 @@ impl Geometry {
         let t = root;
         let point = ray.at(t);
         let outward_normal = (point - *center) / radius;
-        Some(HitRecord::new(t, &point, &outward_normal, ray))
+        let mut hit_record = HitRecord::new(t, &point, &outward_normal, ray);
+        // spherical coordinates, with v going from the bottom pole to the top one
+        let theta = (-outward_normal.y).clamp(-1.0, 1.0).acos();
+        let phi = (-outward_normal.z).atan2(outward_normal.x) + std::f32::consts::PI;
+        hit_record.uv = (
+            phi / (2.0 * std::f32::consts::PI),
+            theta / std::f32::consts::PI,
+        );
+        Some(hit_record)
+    }
+
+    // Möller–Trumbore ray triangle intersection
+    pub(crate) fn hit_triangle(
+        ray: &Ray,
+        t_min: f32,
+        t_max: f32,
+        vertices: &[Vec3; 3],
+        normals: &Option<[Vec3; 3]>,
+        uvs: &Option<[(f32, f32); 3]>,
+    ) -> Option<HitRecord> {
+        let edge_1 = vertices[1] - vertices[0];
+        let edge_2 = vertices[2] - vertices[0];
+        let p = Vec3::cross(&ray.direction, &edge_2);
+        let determinant = Vec3::dot(&edge_1, &p);
+        // the ray is parallel to the plane of the triangle, the determinant scales with the
+        // lengths of both edges and of the direction
+        let scale =
+            edge_1.length_squared() * edge_2.length_squared() * ray.direction.length_squared();
+        if determinant * determinant <= TRIANGLE_EPSILON * TRIANGLE_EPSILON * scale {
+            return None;
+        }
+
+        let inverse_determinant = 1.0 / determinant;
+        let origin_to_vertex = ray.origin - vertices[0];
+        let u = Vec3::dot(&origin_to_vertex, &p) * inverse_determinant;
+        if !(0.0..=1.0).contains(&u) {
+            return None;
+        }
+
+        let q = Vec3::cross(&origin_to_vertex, &edge_1);
+        let v = Vec3::dot(&ray.direction, &q) * inverse_determinant;
+        if v < 0.0 || u + v > 1.0 {
+            return None;
+        }
+
+        let t = Vec3::dot(&edge_2, &q) * inverse_determinant;
+        if t < t_min || t_max < t {
+            return None;
+        }
+
+        let w = 1.0 - u - v;
+        let point = ray.at(t);
+        let outward_normal = Vec3::cross(&edge_1, &edge_2).unit_vector();
+        let mut hit_record = HitRecord::new(t, &point, &outward_normal, ray);
+        hit_record.barycentric = (u, v);
+        if let Some(normals) = normals {
+            let shading_normal = normals[0] * w + normals[1] * u + normals[2] * v;
+            if !shading_normal.near_zero() {
+                hit_record.set_shading_normal(&shading_normal.unit_vector());
+            }
+        }
+        hit_record.uv = match uvs {
+            Some(uvs) => (
+                uvs[0].0 * w + uvs[1].0 * u + uvs[2].0 * v,
+                uvs[0].1 * w + uvs[1].1 * u + uvs[2].1 * v,
+            ),
+            None => (u, v),
+        };
+        Some(hit_record)
+    }
+
+    fn _hit_instance<'a>(
+        ray: &Ray,
+        t_min: f32,
+        t_max: f32,
+        object: &'a Geometry,
+        transform: &Transform,
+    ) -> Option<(HitRecord, &'a Material)> {
+        let object_ray = transform.inverse_transform_ray(ray);
+        let (mut hit_record, material) = object.hit(&object_ray, t_min, t_max)?;
+        // the normal already faces the object space ray, and the inverse transpose keeps the
+        // sign of its dot product with the direction, so the front face stays valid
+        hit_record.point = ray.at(hit_record.t);
+        hit_record.normal = transform.transform_normal(&hit_record.normal).unit_vector();
+        Some((hit_record, material))
     }
 
     fn _bounding_box_sphere(center: &Vec3, radius: f32) -> Option<AABB> {
@@ -69,4 +239,165 @@ impl Geometry {
             maximum: *center + Vec3::new(radius, radius, radius),
         })
     }
+
+    pub(crate) fn bounding_box_triangle(vertices: &[Vec3; 3]) -> Option<AABB> {
+        let bounding_box = AABB::surrounding_box(
+            &AABB::surrounding_box(
+                &AABB::from_point(&vertices[0]),
+                &AABB::from_point(&vertices[1]),
+            ),
+            &AABB::from_point(&vertices[2]),
+        );
+        Some(AABB {
+            minimum: bounding_box.minimum - TRIANGLE_BOX_PADDING,
+            maximum: bounding_box.maximum + TRIANGLE_BOX_PADDING,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn triangle(size: f32) -> [Vec3; 3] {
+        [
+            Vec3::new(-size, -size, 0.0),
+            Vec3::new(size, -size, 0.0),
+            Vec3::new(0.0, size, 0.0),
+        ]
+    }
+
+    #[test]
+    fn hits_tiny_triangles() {
+        for size in [1.0, 1e-3, 1e-5] {
+            let ray = Ray {
+                origin: Vec3::new(0.0, 0.0, 1.0),
+                direction: Vec3::new(0.0, 0.0, -1.0),
+            };
+            let hit_record =
+                Geometry::hit_triangle(&ray, 1e-3, f32::INFINITY, &triangle(size), &None, &None);
+            assert!(hit_record.is_some(), "missed a triangle of size {}", size);
+        }
+    }
+
+    #[test]
+    fn misses_parallel_rays() {
+        let ray = Ray {
+            origin: Vec3::new(-2.0, 0.0, 0.0),
+            direction: Vec3::new(1.0, 0.0, 0.0),
+        };
+        let hit_record =
+            Geometry::hit_triangle(&ray, 1e-3, f32::INFINITY, &triangle(1.0), &None, &None);
+        assert!(hit_record.is_none());
+    }
+
+    #[test]
+    fn rebuilds_shared_meshes_once() {
+        let material = Material::Metal {
+            color: Vec3::new(1.0, 1.0, 1.0),
+            fuzz: 0.0,
+        };
+        let positions = triangle(1.0).to_vec();
+        let mesh = Arc::new(Mesh::new(
+            positions,
+            None,
+            None,
+            vec![[0, 1, 2]],
+            vec![material],
+            None,
+        ));
+        let mut scene = crate::scene::Scene::new();
+        scene.add_object(Geometry::Mesh {
+            mesh: Arc::clone(&mesh),
+        });
+        scene.add_object(Geometry::Instance {
+            object: Arc::new(Geometry::Mesh { mesh }),
+            transform: Transform::translate(&Vec3::new(1.0, 0.0, 0.0)),
+        });
+        scene.build_bvh(SplitStrategy::Midpoint);
+
+        let meshes: Vec<&Arc<Mesh>> = scene
+            .objects
+            .iter()
+            .map(|object| match object {
+                Geometry::Mesh { mesh } => mesh,
+                Geometry::Instance { object, .. } => match object.as_ref() {
+                    Geometry::Mesh { mesh } => mesh,
+                    _ => unreachable!(),
+                },
+                _ => unreachable!(),
+            })
+            .collect();
+        assert_eq!(meshes[0].split_strategy(), SplitStrategy::Midpoint);
+        assert!(Arc::ptr_eq(meshes[0], meshes[1]));
+    }
+
+    #[test]
+    fn hits_instances_under_non_uniform_scales() {
+        let material = Material::Metal {
+            color: Vec3::new(1.0, 1.0, 1.0),
+            fuzz: 0.0,
+        };
+        // an ellipse with x^2 + y^2 / 4 = 1 in the plane z = 0
+        let instance = Geometry::Instance {
+            object: Arc::new(Geometry::Sphere {
+                center: Vec3::new(0.0, 0.0, 0.0),
+                radius: 1.0,
+                material,
+            }),
+            transform: Transform::scale(&Vec3::new(2.0, 1.0, 1.0)).then(&Transform::rotate_z(90.0)),
+        };
+        let ray = Ray {
+            origin: Vec3::new(5.0, 1.0, 0.0),
+            direction: Vec3::new(-1.0, 0.0, 0.0),
+        };
+        let (hit_record, _) = instance.hit(&ray, 1e-3, f32::INFINITY).unwrap();
+
+        let x = 0.75f32.sqrt();
+        assert!((hit_record.point - Vec3::new(x, 1.0, 0.0)).length() < 1e-5);
+        // the gradient of the ellipse rather than the scaled normal of the sphere
+        let normal = Vec3::new(2.0 * x, 0.5, 0.0).unit_vector();
+        assert!((hit_record.normal - normal).length() < 1e-5);
+    }
+
+    #[test]
+    fn instance_boxes_contain_the_transformed_corners() {
+        let material = Material::Metal {
+            color: Vec3::new(1.0, 1.0, 1.0),
+            fuzz: 0.0,
+        };
+        let object = Arc::new(Geometry::Sphere {
+            center: Vec3::new(1.0, -2.0, 3.0),
+            radius: 0.5,
+            material,
+        });
+        let transform = Transform::rotate(&Vec3::new(1.0, 2.0, 3.0), 37.0)
+            .then(&Transform::scale(&Vec3::new(0.3, 3.0, 2.0)))
+            .then(&Transform::translate(&Vec3::new(-4.0, 5.0, 6.0)));
+        let object_box = object.bounding_box().unwrap();
+        let instance = Geometry::Instance { object, transform };
+        let world_box = instance.bounding_box().unwrap();
+
+        for corner in 0..8 {
+            let pick = |bit: usize, minimum: f32, maximum: f32| match corner & bit {
+                0 => minimum,
+                _ => maximum,
+            };
+            let point = transform.transform_point(&Vec3::new(
+                pick(1, object_box.minimum.x, object_box.maximum.x),
+                pick(2, object_box.minimum.y, object_box.maximum.y),
+                pick(4, object_box.minimum.z, object_box.maximum.z),
+            ));
+            let inside = |minimum: f32, value: f32, maximum: f32| {
+                minimum - 1e-4 <= value && value <= maximum + 1e-4
+            };
+            assert!(
+                inside(world_box.minimum.x, point.x, world_box.maximum.x)
+                    && inside(world_box.minimum.y, point.y, world_box.maximum.y)
+                    && inside(world_box.minimum.z, point.z, world_box.maximum.z),
+                "corner {} is outside the box",
+                corner
+            );
+        }
+    }
 }
@@ -0,0 +1,222 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use exr::prelude::f16;
+use image::codecs::hdr::HdrEncoder;
+use image::{ImageError, Rgb};
+
+use crate::{aov::Aov, render::Render, vec_three::Vec3};
+
+#[derive(Debug)]
+pub enum HdrError {
+    Io(io::Error),
+    Image(ImageError),
+    Exr(exr::error::Error),
+}
+
+impl fmt::Display for HdrError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HdrError::Io(error) => write!(f, "{}", error),
+            HdrError::Image(error) => write!(f, "{}", error),
+            HdrError::Exr(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for HdrError {}
+
+impl From<io::Error> for HdrError {
+    fn from(error: io::Error) -> Self {
+        HdrError::Io(error)
+    }
+}
+
+impl From<ImageError> for HdrError {
+    fn from(error: ImageError) -> Self {
+        HdrError::Image(error)
+    }
+}
+
+impl From<exr::error::Error> for HdrError {
+    fn from(error: exr::error::Error) -> Self {
+        HdrError::Exr(error)
+    }
+}
+
+/// Formats keeping the linear radiance of a render, values above 1 included.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HdrFormat {
+    /// OpenEXR with 32 bit float channels.
+    Exr,
+    /// OpenEXR with 16 bit float channels, half the size for about 3 significant digits.
+    ExrHalf,
+    /// Portable float map, uncompressed 32 bit floats.
+    Pfm,
+    /// Radiance RGBE, 8 bit mantissas sharing an exponent.
+    Radiance,
+}
+
+impl HdrFormat {
+    /// Format matching the extension of `path`, 32 bit floats for `.exr`.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Option<HdrFormat> {
+        let extension = path.as_ref().extension()?.to_str()?.to_lowercase();
+        match extension.as_str() {
+            "exr" => Some(HdrFormat::Exr),
+            "pfm" => Some(HdrFormat::Pfm),
+            "hdr" => Some(HdrFormat::Radiance),
+            _ => None,
+        }
+    }
+}
+
+/// Saves the linear colors of `render` without tone mapping them.
+pub fn save_hdr<P: AsRef<Path>>(
+    render: &Render,
+    path: P,
+    format: HdrFormat,
+) -> Result<(), HdrError> {
+    save_colors(
+        render.width(),
+        render.height(),
+        path.as_ref(),
+        format,
+        |x, y| render.color(x, y),
+    )
+}
+
+/// Saves one output variable of `render`, scalars being repeated over the three channels.
+pub fn save_aov<P: AsRef<Path>>(
+    render: &Render,
+    aov: Aov,
+    path: P,
+    format: HdrFormat,
+) -> Result<(), HdrError> {
+    save_colors(
+        render.width(),
+        render.height(),
+        path.as_ref(),
+        format,
+        |x, y| render.aovs(x, y).value(aov),
+    )
+}
+
+fn save_colors<F: Fn(u32, u32) -> Vec3 + Sync>(
+    width: u32,
+    height: u32,
+    path: &Path,
+    format: HdrFormat,
+    pixel: F,
+) -> Result<(), HdrError> {
+    let color = |x: usize, y: usize| pixel(x as u32, y as u32);
+
+    match format {
+        HdrFormat::Exr => {
+            exr::prelude::write_rgb_file(path, width as usize, height as usize, |x, y| {
+                let color = color(x, y);
+                (color.x, color.y, color.z)
+            })?
+        }
+        HdrFormat::ExrHalf => {
+            exr::prelude::write_rgb_file(path, width as usize, height as usize, |x, y| {
+                let color = color(x, y);
+                (
+                    f16::from_f32(color.x),
+                    f16::from_f32(color.y),
+                    f16::from_f32(color.z),
+                )
+            })?
+        }
+        HdrFormat::Pfm => {
+            let mut writer = BufWriter::new(File::create(path)?);
+            // a negative scale means little endian
+            write!(writer, "PF\n{} {}\n-1.0\n", width, height)?;
+            // the rows are stored from the bottom up
+            for y in (0..height).rev() {
+                for x in 0..width {
+                    let color = pixel(x, y);
+                    for value in [color.x, color.y, color.z] {
+                        writer.write_all(&value.to_le_bytes())?;
+                    }
+                }
+            }
+            writer.flush()?;
+        }
+        HdrFormat::Radiance => {
+            let pixels: Vec<Rgb<f32>> = (0..height)
+                .flat_map(|y| (0..width).map(move |x| (x, y)))
+                .map(|(x, y)| {
+                    let color = pixel(x, y);
+                    // RGBE can't store negative values, which filters with negative lobes
+                    // can produce, nor the infinite depth of the pixels that hit nothing
+                    let value = |value: f32| value.clamp(0.0, f32::MAX);
+                    Rgb([value(color.x), value(color.y), value(color.z)])
+                })
+                .collect();
+            let writer = BufWriter::new(File::create(path)?);
+            HdrEncoder::new(writer).encode(&pixels, width as usize, height as usize)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::codecs::hdr::HdrDecoder;
+    use std::fs;
+    use std::io::BufReader;
+
+    const WIDTH: u32 = 3;
+    const HEIGHT: u32 = 2;
+
+    // a different color in every pixel, with values far above 1 and far below it
+    fn color(x: u32, y: u32) -> Vec3 {
+        let value = (1 + x + WIDTH * y) as f32;
+        Vec3::new(value * 0.01, value, value * value * 100.0)
+    }
+
+    fn saved(name: &str, format: HdrFormat) -> Vec<u8> {
+        let path = std::env::temp_dir().join(format!("ray_tracing_hdr_{}", name));
+        save_colors(WIDTH, HEIGHT, &path, format, color).unwrap();
+        fs::read(&path).unwrap()
+    }
+
+    #[test]
+    fn pfm_files_read_back_exactly() {
+        let bytes = saved("round_trip.pfm", HdrFormat::Pfm);
+        let header = b"PF\n3 2\n-1.0\n";
+        assert_eq!(&bytes[..header.len()], header);
+
+        let values: Vec<f32> = bytes[header.len()..]
+            .chunks_exact(4)
+            .map(|value| f32::from_le_bytes([value[0], value[1], value[2], value[3]]))
+            .collect();
+        assert_eq!(values.len(), (WIDTH * HEIGHT * 3) as usize);
+        for (index, pixel) in values.chunks_exact(3).enumerate() {
+            let x = index as u32 % WIDTH;
+            // the bottom row comes first
+            let y = HEIGHT - 1 - index as u32 / WIDTH;
+            assert_eq!(Vec3::new(pixel[0], pixel[1], pixel[2]), color(x, y));
+        }
+    }
+
+    #[test]
+    fn radiance_files_read_back_to_8_bits() {
+        let bytes = saved("round_trip.hdr", HdrFormat::Radiance);
+        let decoder = HdrDecoder::new(BufReader::new(bytes.as_slice())).unwrap();
+        let metadata = decoder.metadata();
+        assert_eq!((metadata.width, metadata.height), (WIDTH, HEIGHT));
+
+        let pixels = decoder.read_image_hdr().unwrap();
+        for (index, pixel) in pixels.iter().enumerate() {
+            let expected = color(index as u32 % WIDTH, index as u32 / WIDTH);
+            // the channels share the exponent of the largest one
+            let tolerance = expected.z / 128.0;
+            assert!((Vec3::new(pixel[0], pixel[1], pixel[2]) - expected).length() < tolerance);
+        }
+    }
+}
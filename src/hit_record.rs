@@ -1,9 +1,18 @@
 use crate::{ray::Ray, vec_three::Vec3};
 
+#[derive(Clone, Copy)]
 pub struct HitRecord {
     pub point: Vec3,
     pub normal: Vec3,
     pub t: f32,
+    /// Surface coordinates of the hit point, used for texturing.
+    pub uv: (f32, f32),
+    /// Weights of the second and third vertex when a triangle is hit; the weight of the first
+    /// vertex is `1 - u - v`. Other geometries leave it at zero.
+    pub barycentric: (f32, f32),
+    /// Index of the material hit among the ones of the object, which only meshes have several
+    /// of. Other geometries leave it at zero.
+    pub material_index: u32,
     front_face: bool,
 }
 
@@ -22,6 +31,9 @@ impl HitRecord {
             t,
             point: *point,
             normal,
+            uv: (0.0, 0.0),
+            barycentric: (0.0, 0.0),
+            material_index: 0,
             front_face,
         }
     }
@@ -29,4 +41,13 @@ impl HitRecord {
     pub fn is_front_face(&self) -> bool {
         self.front_face
     }
+
+    /// Replaces the normal with an interpolated shading normal while keeping it on the side
+    /// the ray came from, as decided by the geometric normal passed to `new`.
+    pub fn set_shading_normal(&mut self, outward_normal: &Vec3) {
+        self.normal = match self.front_face {
+            true => *outward_normal,
+            false => -*outward_normal,
+        };
+    }
 }
@@ -1,11 +1,11 @@
-use crate::vec_three::Vec3;
+use crate::{tone_map::linear_to_srgb, vec_three::Vec3};
 use image::{Rgb, RgbImage};
 
-pub fn write_color(img: &mut RgbImage, x: u32, y: u32, color: &Vec3, samples_per_pixel: u32) -> () {
-    let scale = 1.0 / (samples_per_pixel as f32);
-    let r = (scale * color.x).sqrt();
-    let g = (scale * color.y).sqrt();
-    let b = (scale * color.z).sqrt();
+/// Writes a linear color in [0, 1] encoded as sRGB, values outside of the range are clipped.
+pub fn write_color(img: &mut RgbImage, x: u32, y: u32, color: &Vec3) {
+    let r = linear_to_srgb(color.x.clamp(0.0, 1.0));
+    let g = linear_to_srgb(color.y.clamp(0.0, 1.0));
+    let b = linear_to_srgb(color.z.clamp(0.0, 1.0));
 
     let ir = (255.99 * r.clamp(0.0, 0.999)) as u8;
     let ig = (255.99 * g.clamp(0.0, 0.999)) as u8;
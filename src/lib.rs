@@ -2,161 +2,721 @@ extern crate image as image_crate;
 extern crate rand;
 
 pub mod aabb;
+pub mod aov;
+pub mod background;
+pub mod bvh;
 pub mod camera;
+pub mod denoise;
+pub mod distribution;
+pub mod environment;
+pub mod film;
+pub mod filter;
 pub mod geometry;
+pub mod hdr;
 pub mod hit_record;
 pub mod image;
 pub mod material;
+pub mod mesh;
+pub mod obj;
 pub mod ray;
+pub mod render;
+pub mod sampler;
 pub mod scene;
+pub mod scene_file;
+pub mod settings;
+pub mod texture;
 pub mod thread_pool;
+pub mod tile;
+pub mod tone_map;
+pub mod transform;
 pub mod vec_three;
 
-use std::sync::{Arc, Mutex};
+use std::collections::BTreeMap;
+use std::io::Stdout;
+use std::ops::ControlFlow;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::Arc;
+use std::time::Duration;
 
-use crate::{image::write_color, thread_pool::ThreadPool};
+use crate::{
+    aov::{AovPixel, AovSample, Aovs},
+    thread_pool::ThreadPool,
+    tile::{tiles, Tile},
+};
 use camera::Camera;
-use image_crate::RgbImage;
+use denoise::denoise;
+use film::Film;
+use hit_record::HitRecord;
+use material::Material;
 use pbr::ProgressBar;
-use rand::Rng;
 use ray::Ray;
+use render::Render;
+use sampler::Sampler;
 use scene::Scene;
+use settings::{RenderSettings, SettingsError};
 use vec_three::Vec3;
 
-pub const IMAGE_ASPECT_RATIO: f32 = 3.0 / 2.0;
-pub const IMAGE_WIDTH: u32 = 1200;
-pub const IMAGE_HEIGHT: u32 = ((IMAGE_WIDTH as f32) / IMAGE_ASPECT_RATIO) as u32;
-pub const SAMPLES_PER_PIXEL: u32 = 100;
-const PB_INCREMENT: u32 = 1000;
-const MAX_RAYS: u8 = 50;
-const NUM_CHUNKS: u8 = 80;
-const NUM_THREADS: usize = 10;
-
-const SKY_START_COLOR: Vec3 = Vec3 {
-    x: 0.5,
-    y: 0.7,
-    z: 1.0,
-};
-const SKY_END_COLOR: Vec3 = Vec3 {
-    x: 1.0,
-    y: 1.0,
-    z: 1.0,
-};
+const PB_INCREMENT: u64 = 1000;
+// how often the progress bar is refreshed while waiting for tiles
+const PB_REFRESH: Duration = Duration::from_millis(100);
+
+// pixels whose mean brightness is below this have their noise measured against it instead, so
+// that almost black pixels don't need countless samples
+const DARK_LUMINANCE: f32 = 0.01;
+
+// samples taken inside one pixel so far
+#[derive(Clone, Copy)]
+struct Pixel {
+    samples: u32,
+    // whether the pixel still gets samples, adaptive sampling stops the converged ones
+    active: bool,
+    // running mean and sum of squared differences of the brightness of the samples
+    mean: f32,
+    m2: f32,
+}
+
+impl Pixel {
+    fn new() -> Pixel {
+        Pixel {
+            samples: 0,
+            active: true,
+            mean: 0.0,
+            m2: 0.0,
+        }
+    }
+
+    // Welford's online algorithm
+    fn add(&mut self, color: Vec3) {
+        self.samples += 1;
+
+        let luminance = color.luminance();
+        let delta = luminance - self.mean;
+        self.mean += delta / self.samples as f32;
+        self.m2 += delta * (luminance - self.mean);
+    }
+
+    // variance of the mean brightness, the square of its standard error
+    fn mean_variance(&self) -> Option<f32> {
+        // the variance needs at least two samples
+        if self.samples < 2 {
+            return None;
+        }
+
+        let variance = self.m2 / (self.samples - 1) as f32;
+        Some(variance / self.samples as f32)
+    }
+
+    fn converged(&self, noise_threshold: f32) -> bool {
+        match self.mean_variance() {
+            Some(mean_variance) => {
+                mean_variance.sqrt() / self.mean.max(DARK_LUMINANCE) < noise_threshold
+            }
+            None => false,
+        }
+    }
+}
+
+// pixels of one tile, rendered by a single job and merged once into the accumulation buffers
+struct RenderedTile {
+    // position of the tile in the image, row by row. The tiles are merged in that order so
+    // that the sums depend neither on the order the tiles are handed out in nor on which one
+    // is done first
+    index: usize,
+    tile: Tile,
+    pixels: Vec<Pixel>,
+    aovs: Vec<AovPixel>,
+    // the tile and the pixels around it reached by the filter
+    film: Film,
+}
+
+// what the camera ray of a sample hit, turned into an `AovSample` once it is traced
+struct FirstHit<'a> {
+    object: usize,
+    hit_record: HitRecord,
+    material: &'a Material,
+}
 
-const T_MIN: f32 = 0.001;
-const T_MAX: f32 = f32::MAX;
+/// Renders the whole image with `settings.samples_per_pixel` samples per pixel, or fewer for
+/// the pixels that converge earlier when `settings.noise_threshold` is set.
+pub fn ray_trace(
+    camera: &Camera,
+    world: &Scene,
+    settings: &RenderSettings,
+) -> Result<Render, SettingsError> {
+    if settings.noise_threshold.is_some() {
+        // adaptive sampling picks the pixels that still need samples between passes
+        return ray_trace_progressive(camera, world, settings, |_, _| ControlFlow::Continue(()));
+    }
+    settings.validate()?;
 
-pub fn ray_trace(camera: &Camera, world: &Scene, img: &mut RgbImage) {
-    let pb = Arc::new(Mutex::new(ProgressBar::new(
-        (IMAGE_WIDTH * IMAGE_HEIGHT * SAMPLES_PER_PIXEL / PB_INCREMENT) as u64,
-    )));
-    let count = Arc::new(Mutex::new(0));
+    let mut renderer = Renderer::new(camera, world, settings);
+    renderer.render_pass(settings.samples_per_pixel);
+    Ok(renderer.render())
+}
 
-    let chunk_size_width = IMAGE_WIDTH / (NUM_CHUNKS as u32);
-    let chunk_size_height = IMAGE_HEIGHT / (NUM_CHUNKS as u32);
+/// Renders the whole image in passes that double the number of samples per pixel (1, 2, 4...)
+/// up to `settings.samples_per_pixel`, calling `on_pass` with the image so far and its number
+/// of samples per pixel after each pass.
+///
+/// Rendering stops early when `on_pass` returns `ControlFlow::Break`. Since every sample has
+/// its own random numbers, the final image is the same as the one of `ray_trace` up to the
+/// rounding of the sums. With adaptive sampling, the pixels that converged are left out of the
+/// following passes.
+pub fn ray_trace_progressive<F>(
+    camera: &Camera,
+    world: &Scene,
+    settings: &RenderSettings,
+    mut on_pass: F,
+) -> Result<Render, SettingsError>
+where
+    F: FnMut(&Render, u32) -> ControlFlow<()>,
+{
+    settings.validate()?;
 
-    let pool = ThreadPool::new(NUM_THREADS);
+    let mut renderer = Renderer::new(camera, world, settings);
+    let mut render = renderer.render();
+    while renderer.samples < settings.samples_per_pixel
+        && renderer.pixels.iter().any(|pixel| pixel.active)
+    {
+        let samples = renderer
+            .samples
+            .max(1)
+            .min(settings.samples_per_pixel - renderer.samples);
+        renderer.render_pass(samples);
 
-    let image_buffer = Arc::new(Mutex::new(vec![
-        vec![Vec3::zero(); IMAGE_HEIGHT as usize];
-        IMAGE_WIDTH as usize
-    ]));
-    let camera = Arc::new(Mutex::new(camera.clone()));
-    let mut new_world = Scene::new();
-    for object in world.objects.iter() {
-        new_world.add_object(object.clone());
+        render = renderer.render();
+        if on_pass(&render, renderer.samples).is_break() {
+            break;
+        }
     }
-    let new_world = Arc::new(Mutex::new(new_world));
 
-    for chunk_x in 0..NUM_CHUNKS {
-        for chunk_y in 0..NUM_CHUNKS {
-            let camera = Arc::clone(&camera);
-            let new_world = Arc::clone(&new_world);
-            let image_buffer = Arc::clone(&image_buffer);
+    Ok(render)
+}
 
-            let count = Arc::clone(&count);
-            let pb = Arc::clone(&pb);
+// the worker threads and the samples accumulated so far, kept from one pass to the next
+struct Renderer {
+    pool: ThreadPool,
+    camera: Camera,
+    world: Arc<Scene>,
+    settings: Arc<RenderSettings>,
+    pb: ProgressBar<Stdout>,
+    count: Arc<AtomicU64>,
+    pixels: Vec<Pixel>,
+    // empty when neither the settings nor the denoiser need the AOVs
+    aovs: Vec<AovPixel>,
+    film: Film,
+    samples: u32,
+}
 
-            pool.execute(move || {
-                let start_x = chunk_size_width * chunk_x as u32;
-                let end_x = start_x + chunk_size_width;
-                let start_y = chunk_size_height * chunk_y as u32;
-                let end_y = start_y + chunk_size_height;
-                let mut rng = rand::thread_rng();
+impl Renderer {
+    fn new(camera: &Camera, world: &Scene, settings: &RenderSettings) -> Renderer {
+        let pb = ProgressBar::new(
+            settings.width as u64 * settings.height as u64 * settings.samples_per_pixel as u64
+                / PB_INCREMENT,
+        );
 
-                let mut image_buffer = image_buffer.lock().unwrap();
-                let mut count = count.lock().unwrap();
-                let mut pb = pb.lock().unwrap();
+        let mut world = world.clone();
+        if !world.has_bvh() {
+            world.build_bvh(settings.split_strategy);
+        }
+        let aov_pixels = match settings.aovs || settings.denoise {
+            true => settings.width as usize * settings.height as usize,
+            false => 0,
+        };
 
-                for i in start_x..end_x {
-                    for j in start_y..end_y {
-                        let mut color = Vec3::zero();
+        Renderer {
+            pool: ThreadPool::new(settings.num_threads),
+            camera: *camera,
+            world: Arc::new(world),
+            settings: Arc::new(settings.clone()),
+            pb,
+            count: Arc::new(AtomicU64::new(0)),
+            pixels: vec![Pixel::new(); settings.width as usize * settings.height as usize],
+            aovs: vec![AovPixel::new(); aov_pixels],
+            film: Film::new(
+                0,
+                0,
+                settings.width,
+                settings.height,
+                settings.filter,
+                filter_radius(settings),
+            ),
+            samples: 0,
+        }
+    }
+
+    // adds `samples` more samples to every active pixel
+    fn render_pass(&mut self, samples: u32) {
+        let width = self.settings.width;
+        let height = self.settings.height;
+        let filter_radius = filter_radius(&self.settings);
+        let margin = Film::margin(filter_radius);
+        let (sender, receiver) = mpsc::channel();
+        let tile_size = self.settings.tile_size;
+        let columns = width.div_ceil(tile_size);
 
-                        for _ in 0..SAMPLES_PER_PIXEL {
-                            *count += 1;
+        // positions of the tiles in the image, row by row, which is the order they are merged in
+        let mut indices = vec![];
+        for tile in tiles(width, height, tile_size, self.settings.tile_order) {
+            // the jobs only ever read the scene, the camera and the settings, so they are
+            // shared without any locking
+            let camera = self.camera;
+            let world = Arc::clone(&self.world);
+            let settings = Arc::clone(&self.settings);
+            let count = Arc::clone(&self.count);
+            let sender = sender.clone();
+            let mut pixels: Vec<Pixel> = tile_pixels(&self.pixels, width, &tile);
+            if !pixels.iter().any(|pixel| pixel.active) {
+                continue;
+            }
+            let mut aovs = match self.aovs.is_empty() {
+                true => vec![],
+                false => tile_pixels(&self.aovs, width, &tile),
+            };
+            let film_x = tile.x.saturating_sub(margin);
+            let film_y = tile.y.saturating_sub(margin);
+            let mut film = Film::new(
+                film_x,
+                film_y,
+                (tile.x + tile.width + margin).min(width) - film_x,
+                (tile.y + tile.height + margin).min(height) - film_y,
+                settings.filter,
+                filter_radius,
+            );
+            let index = (tile.y / tile_size * columns + tile.x / tile_size) as usize;
+            indices.push(index);
 
-                            let random_num: f32 = rng.gen();
+            self.pool.execute(move || {
+                let mut sampler = settings
+                    .sampler
+                    .sampler(settings.seed, settings.samples_per_pixel);
+                let mut aovs_iter = aovs.iter_mut();
 
-                            let u = (i as f32 + random_num) / ((IMAGE_WIDTH as f32) - 1.0);
-                            let v = (j as f32 + random_num) / ((IMAGE_HEIGHT as f32) - 1.0);
+                for (y, row) in (tile.y..).zip(pixels.chunks_mut(tile.width as usize)) {
+                    for (x, pixel) in (tile.x..).zip(row.iter_mut()) {
+                        let mut aov_pixel = aovs_iter.next();
+                        if !pixel.active {
+                            continue;
+                        }
 
-                            // limit the number rays to be 10
-                            let mut depth: u8 = MAX_RAYS;
-                            let camera = camera.lock().unwrap();
-                            let new_world = new_world.lock().unwrap();
-                            let ray = camera.get_origin_ray(u, v);
-                            color = color + ray_color(&ray, &new_world, &mut depth);
+                        for sample in pixel.samples..pixel.samples + samples {
+                            sampler.start_sample(x, y, sample);
+                            let (pixel_x, pixel_y) = sampler.next_2d();
+                            let film_x = x as f32 + pixel_x;
+                            let film_y = y as f32 + pixel_y;
 
-                            if *count % PB_INCREMENT == 0 {
-                                pb.inc();
+                            // the camera has v growing upwards while the image rows go down
+                            let u = film_x / width as f32;
+                            let v = 1.0 - film_y / height as f32;
+
+                            let mut depth: u8 = settings.max_depth;
+                            let ray = camera.get_origin_ray(u, v, sampler.next_2d());
+                            let mut first_hit = None;
+                            let color = _ray_color(
+                                &ray,
+                                &world,
+                                &settings,
+                                &mut depth,
+                                None,
+                                &mut *sampler,
+                                aov_pixel.as_ref().map(|_| &mut first_hit),
+                            );
+                            pixel.add(color);
+                            if let Some(aov_pixel) = &mut aov_pixel {
+                                aov_pixel.add(first_hit.map(|first_hit| AovSample {
+                                    albedo: first_hit.material.albedo(&first_hit.hit_record),
+                                    normal: first_hit.hit_record.normal,
+                                    position: first_hit.hit_record.point,
+                                    depth: first_hit.hit_record.t,
+                                    object_id: first_hit.object as u32 + 1,
+                                    material_id:
+                                        world.material_id(first_hit.object, &first_hit.hit_record),
+                                }));
                             }
+                            film.add_sample(film_x, film_y, color);
                         }
 
-                        image_buffer[i as usize][j as usize] = color;
+                        count.fetch_add(samples as u64, Ordering::Relaxed);
                     }
                 }
+
+                // the receiver only goes away if the render was abandoned
+                let _ = sender.send(RenderedTile {
+                    index,
+                    tile,
+                    pixels,
+                    aovs,
+                    film,
+                });
             });
         }
+
+        // only the jobs hold a sender now, the channel disconnects once the last one is done
+        drop(sender);
+
+        indices.sort_unstable();
+        let mut next = indices.iter().peekable();
+        let mut done = BTreeMap::new();
+        loop {
+            match receiver.recv_timeout(PB_REFRESH) {
+                Ok(rendered_tile) => {
+                    done.insert(rendered_tile.index, rendered_tile);
+                    while let Some(RenderedTile {
+                        tile,
+                        pixels,
+                        aovs,
+                        film,
+                        ..
+                    }) = next.peek().and_then(|&index| done.remove(index))
+                    {
+                        set_tile_pixels(&mut self.pixels, width, &tile, &pixels);
+                        set_tile_pixels(&mut self.aovs, width, &tile, &aovs);
+                        self.film.merge(&film);
+                        next.next();
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+            self.pb
+                .set(self.count.load(Ordering::Relaxed) / PB_INCREMENT);
+        }
+
+        self.samples += samples;
+        self.update_active_pixels();
     }
 
-    for i in 0..IMAGE_WIDTH - 1 {
-        for j in 0..IMAGE_HEIGHT - 1 {
-            // subtract IMAGE_HEIGHT - j as the we want to move the origin from top left to bottom left
-            let image_buffer = image_buffer.lock().unwrap();
-            let color = image_buffer[i as usize][j as usize];
-            write_color(
-                img,
-                i as u32,
-                IMAGE_HEIGHT - 1 - j as u32,
-                &color,
-                SAMPLES_PER_PIXEL,
+    // stops sampling the pixels that converged along with all their neighbours, looking at the
+    // neighbours as well makes up for the pixels whose first samples all happened to agree
+    fn update_active_pixels(&mut self) {
+        let noise_threshold = match self.settings.noise_threshold {
+            Some(noise_threshold) => noise_threshold,
+            None => return,
+        };
+        if self.samples < self.settings.min_samples_per_pixel {
+            return;
+        }
+
+        let width = self.settings.width as usize;
+        let height = self.settings.height as usize;
+        let noisy: Vec<bool> = self
+            .pixels
+            .iter()
+            .map(|pixel| !pixel.converged(noise_threshold))
+            .collect();
+        for y in 0..height {
+            for x in 0..width {
+                let pixel = &mut self.pixels[y * width + x];
+                pixel.active = pixel.active
+                    && (y.saturating_sub(1)..(y + 2).min(height)).any(|y| {
+                        (x.saturating_sub(1)..(x + 2).min(width)).any(|x| noisy[y * width + x])
+                    });
+            }
+        }
+    }
+
+    fn render(&self) -> Render {
+        let width = self.settings.width;
+        let height = self.settings.height;
+        let mut colors: Vec<Vec3> = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| self.film.color(x, y))
+            .collect();
+        let aovs: Vec<Aovs> = match self.aovs.is_empty() {
+            true => vec![AovPixel::new().aovs(); width as usize * height as usize],
+            false => self.aovs.iter().map(|aov_pixel| aov_pixel.aovs()).collect(),
+        };
+
+        if self.settings.denoise {
+            let variances: Vec<f32> = self
+                .pixels
+                .iter()
+                .map(|pixel| {
+                    // with a single sample, the brightness is only known to be about that
+                    // bright
+                    pixel
+                        .mean_variance()
+                        .unwrap_or_else(|| pixel.mean.max(DARK_LUMINANCE).powi(2))
+                })
+                .collect();
+            colors = denoise(
+                width,
+                height,
+                &colors,
+                &variances,
+                &aovs,
+                self.settings.num_threads,
             );
         }
+
+        Render::new(
+            width,
+            height,
+            colors,
+            self.pixels.iter().map(|pixel| pixel.samples).collect(),
+            aovs,
+        )
     }
 }
 
-pub fn ray_color(ray: &Ray, world: &Scene, depth: &mut u8) -> Vec3 {
+// copy of the values of the pixels of `tile` out of a buffer of the whole image
+fn tile_pixels<T: Copy>(buffer: &[T], width: u32, tile: &Tile) -> Vec<T> {
+    (tile.y..tile.y + tile.height)
+        .flat_map(|y| {
+            let row = (y * width + tile.x) as usize;
+            buffer[row..row + tile.width as usize].iter().copied()
+        })
+        .collect()
+}
+
+fn set_tile_pixels<T: Copy>(buffer: &mut [T], width: u32, tile: &Tile, values: &[T]) {
+    for (y, row) in (tile.y..).zip(values.chunks(tile.width as usize)) {
+        let start = (y * width + tile.x) as usize;
+        buffer[start..start + row.len()].copy_from_slice(row);
+    }
+}
+
+fn filter_radius(settings: &RenderSettings) -> f32 {
+    settings
+        .filter_radius
+        .unwrap_or_else(|| settings.filter.default_radius())
+}
+
+pub fn ray_color<S: Sampler + ?Sized>(
+    ray: &Ray,
+    world: &Scene,
+    settings: &RenderSettings,
+    depth: &mut u8,
+    sampler: &mut S,
+) -> Vec3 {
+    _ray_color(ray, world, settings, depth, None, sampler, None)
+}
+
+// `scattering_pdf` is the density with which the previous bounce picked `ray`, it is `None`
+// for camera rays and for materials that can't be combined with light sampling. `first_hit`
+// is set to what `ray` hits, if anything
+fn _ray_color<'a, S: Sampler + ?Sized>(
+    ray: &Ray,
+    world: &'a Scene,
+    settings: &RenderSettings,
+    depth: &mut u8,
+    scattering_pdf: Option<f32>,
+    sampler: &mut S,
+    first_hit: Option<&mut Option<FirstHit<'a>>>,
+) -> Vec3 {
     if *depth == 0 {
         return Vec3::zero();
     }
 
-    if let Some((hit_record, material)) = world.hit(ray, T_MIN, T_MAX) {
-        return match material.scatter(ray, &hit_record) {
+    if let Some((object, hit_record, material)) =
+        world.hit_object(ray, settings.t_min, settings.t_max)
+    {
+        if let Some(first_hit) = first_hit {
+            *first_hit = Some(FirstHit {
+                object,
+                hit_record,
+                material,
+            });
+        }
+        let emitted = material.emitted(&hit_record)
+            + sample_environment(world, settings, &hit_record, material, sampler);
+        return match material.scatter(ray, &hit_record, sampler) {
             Some((attenuation, scattered_ray)) => {
                 *depth -= 1;
-                ray_color(&scattered_ray, world, depth) * attenuation
+                let scattering_pdf = material.scattering_pdf(&hit_record, &scattered_ray.direction);
+                emitted
+                    + _ray_color(
+                        &scattered_ray,
+                        world,
+                        settings,
+                        depth,
+                        scattering_pdf,
+                        sampler,
+                        None,
+                    ) * attenuation
             }
-            None => Vec3::zero(),
+            None => emitted,
         };
     }
 
-    sky_color(&ray)
+    let background = world.background.color(ray);
+    match (world.environment(), scattering_pdf) {
+        // the environment was also sampled directly at the previous hit, so weight this path
+        // against the light sample
+        (Some(environment), Some(scattering_pdf)) => {
+            background * power_heuristic(scattering_pdf, environment.pdf(&ray.direction))
+        }
+        _ => background,
+    }
 }
 
-fn sky_color(ray: &Ray) -> Vec3 {
-    let unit_direction = ray.direction.unit_vector();
-    // normalize the t to be a value between 0 and 1
-    let t = 0.5 * (unit_direction.y + 1.0);
-    // interpolate from sky start color to end color using the y
-    SKY_END_COLOR * (1.0 - t) + SKY_START_COLOR * t
+// next event estimation towards the environment map, weighted with multiple importance
+// sampling against the material sample that continues the path
+fn sample_environment<S: Sampler + ?Sized>(
+    world: &Scene,
+    settings: &RenderSettings,
+    hit_record: &HitRecord,
+    material: &Material,
+    sampler: &mut S,
+) -> Vec3 {
+    let environment = match world.environment() {
+        Some(environment) => environment,
+        None => return Vec3::zero(),
+    };
+
+    let (u, v) = sampler.next_2d();
+    let (direction, radiance, light_pdf) = match environment.sample(u, v) {
+        Some(sample) => sample,
+        None => return Vec3::zero(),
+    };
+    let scattering_pdf = match material.scattering_pdf(hit_record, &direction) {
+        Some(scattering_pdf) if scattering_pdf > 0.0 => scattering_pdf,
+        _ => return Vec3::zero(),
+    };
+
+    let shadow_ray = Ray {
+        origin: hit_record.point,
+        direction,
+    };
+    if world
+        .hit(&shadow_ray, settings.t_min, settings.t_max)
+        .is_some()
+    {
+        return Vec3::zero();
+    }
+
+    material.evaluate(hit_record, &direction)
+        * radiance
+        * (power_heuristic(light_pdf, scattering_pdf) / light_pdf)
+}
+
+fn power_heuristic(pdf: f32, other_pdf: f32) -> f32 {
+    let pdf_squared = pdf * pdf;
+    let total = pdf_squared + other_pdf * other_pdf;
+    match total > 0.0 {
+        true => pdf_squared / total,
+        false => 0.0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        background::Background, filter::Filter, geometry::Geometry, scene_file::SceneDescription,
+        tile::TileOrder,
+    };
+
+    // colors of a small render of the example scene, with `change` applied to its settings
+    fn render_example<F: FnOnce(&mut RenderSettings)>(change: F) -> Vec<Vec3> {
+        let description =
+            SceneDescription::load(concat!(env!("CARGO_MANIFEST_DIR"), "/scenes/example.toml"))
+                .unwrap();
+        let mut settings = RenderSettings {
+            width: 23,
+            height: 17,
+            samples_per_pixel: 4,
+            max_depth: 4,
+            ..description.settings
+        };
+        change(&mut settings);
+
+        let camera = description
+            .camera
+            .camera(settings.width as f32 / settings.height as f32);
+        let render = ray_trace(&camera, &description.scene, &settings).unwrap();
+        (0..settings.height)
+            .flat_map(|y| (0..settings.width).map(move |x| (x, y)))
+            .map(|(x, y)| render.color(x, y))
+            .collect()
+    }
+
+    #[test]
+    fn renders_the_same_image_with_any_number_of_threads() {
+        let image = render_example(|settings| settings.num_threads = 1);
+        for num_threads in [2, 5] {
+            assert_eq!(
+                render_example(|settings| settings.num_threads = num_threads),
+                image
+            );
+        }
+        assert_ne!(
+            render_example(|settings| {
+                settings.num_threads = 1;
+                settings.seed += 1;
+            }),
+            image
+        );
+    }
+
+    #[test]
+    fn renders_the_same_image_with_any_tiles() {
+        let render = |tile_order, tile_size| {
+            render_example(|settings| {
+                settings.filter = Filter::Gaussian;
+                settings.tile_order = tile_order;
+                settings.tile_size = tile_size;
+            })
+        };
+        for tile_size in [16, 5] {
+            let image = render(TileOrder::Scanline, tile_size);
+            assert_eq!(render(TileOrder::Hilbert, tile_size), image);
+            assert_eq!(render(TileOrder::Spiral, tile_size), image);
+        }
+
+        // other tiles only group the sums along their edges differently
+        let image = render(TileOrder::Scanline, 16);
+        for (color, other) in image.iter().zip(render(TileOrder::Scanline, 5)) {
+            assert!((*color - other).length() <= 1e-5 * color.length().max(1.0));
+        }
+    }
+
+    #[test]
+    fn stops_sampling_flat_regions_only() {
+        // a sphere on the left lit by a large light behind the camera, in front of a plain sky
+        // that every sample sees the same
+        let mut scene = Scene::new();
+        scene.background = Background::Color(Vec3::new(0.5, 0.5, 0.5));
+        scene.add_object(Geometry::Sphere {
+            center: Vec3::new(-0.6, 0.0, -3.0),
+            radius: 1.0,
+            material: Material::Lambert {
+                texture: Vec3::new(0.8, 0.8, 0.8).into(),
+            },
+        });
+        scene.add_object(Geometry::Sphere {
+            center: Vec3::new(0.0, 0.0, 6.0),
+            radius: 4.0,
+            material: Material::DiffuseLight {
+                color: Vec3::new(1.0, 1.0, 1.0),
+                intensity: 5.0,
+            },
+        });
+        let settings = RenderSettings {
+            width: 20,
+            height: 20,
+            samples_per_pixel: 64,
+            min_samples_per_pixel: 8,
+            noise_threshold: Some(0.01),
+            max_depth: 4,
+            num_threads: 2,
+            ..RenderSettings::default()
+        };
+        let camera = Camera::new(
+            1.0,
+            40.0,
+            &Vec3::zero(),
+            &Vec3::new(0.0, 0.0, -1.0),
+            &Vec3::new(0.0, 1.0, 0.0),
+            0.0,
+            1.0,
+        );
+
+        let render = ray_trace(&camera, &scene, &settings).unwrap();
+        for y in 0..20 {
+            assert_eq!(render.samples(19, y), 8, "sky pixel at (19, {})", y);
+        }
+        assert_eq!(render.samples(4, 10), 64);
+    }
 }
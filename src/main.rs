@@ -1,19 +1,215 @@
 extern crate image;
 
-use std::fs::create_dir;
-use std::path::Path;
+use std::error::Error;
+use std::fs::create_dir_all;
+use std::io;
+use std::ops::ControlFlow;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::Arc;
 use std::time::Instant;
 
-use image::{ImageBuffer, RgbImage};
-use rand::Rng;
+use clap::{ArgGroup, Parser, ValueEnum};
+use image::{ImageFormat, RgbImage};
+use rand::{rngs::StdRng, Rng, SeedableRng};
 use ray_tracing::{
-    camera::Camera, geometry::Geometry, material::Material, ray_trace, scene::Scene,
-    vec_three::Vec3, IMAGE_ASPECT_RATIO, IMAGE_HEIGHT, IMAGE_WIDTH,
+    aov::Aov,
+    background::Background,
+    bvh::SplitStrategy,
+    camera::CameraSettings,
+    filter::Filter,
+    geometry::Geometry,
+    hdr::{save_aov, save_hdr, HdrFormat},
+    material::Material,
+    mesh::Mesh,
+    obj::ObjModel,
+    ray_trace, ray_trace_progressive,
+    render::Render,
+    sampler::SamplerKind,
+    scene::Scene,
+    scene_file::SceneDescription,
+    settings::RenderSettings,
+    tile::TileOrder,
+    tone_map::ToneMap,
+    transform::Transform,
+    vec_three::Vec3,
 };
 
-fn make_scene() -> Scene {
-    let mut rng = rand::thread_rng();
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum BuiltinScene {
+    /// Spheres with random materials scattered around three large ones
+    Random,
+    /// Cornell box lit only by an area light
+    Cornell,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum OutputFormat {
+    Png,
+    Jpeg,
+    Bmp,
+    Tga,
+    Tiff,
+    Pnm,
+    /// OpenEXR with 32 bit floats
+    Exr,
+    /// OpenEXR with 16 bit floats
+    ExrHalf,
+    /// Portable float map
+    Pfm,
+    /// Radiance RGBE
+    Hdr,
+}
+
+// 8 bit images are tone mapped, high dynamic range ones keep the linear radiance
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Output {
+    Image(ImageFormat),
+    Hdr(HdrFormat),
+}
+
+impl From<OutputFormat> for Output {
+    fn from(format: OutputFormat) -> Self {
+        match format {
+            OutputFormat::Png => Output::Image(ImageFormat::Png),
+            OutputFormat::Jpeg => Output::Image(ImageFormat::Jpeg),
+            OutputFormat::Bmp => Output::Image(ImageFormat::Bmp),
+            OutputFormat::Tga => Output::Image(ImageFormat::Tga),
+            OutputFormat::Tiff => Output::Image(ImageFormat::Tiff),
+            OutputFormat::Pnm => Output::Image(ImageFormat::Pnm),
+            OutputFormat::Exr => Output::Hdr(HdrFormat::Exr),
+            OutputFormat::ExrHalf => Output::Hdr(HdrFormat::ExrHalf),
+            OutputFormat::Pfm => Output::Hdr(HdrFormat::Pfm),
+            OutputFormat::Hdr => Output::Hdr(HdrFormat::Radiance),
+        }
+    }
+}
+
+/// Render a scene with a path tracer and save the result as an image.
+#[derive(Debug, Parser)]
+#[command(version, group(ArgGroup::new("input").args(["scene", "builtin"])))]
+struct Cli {
+    /// Scene description (.toml) or Wavefront OBJ file to render; OBJ files are framed by an
+    /// automatically placed camera
+    #[arg(short, long, value_name = "FILE")]
+    scene: Option<PathBuf>,
+
+    /// Built-in scene to render when no scene file is given
+    #[arg(short, long, value_enum, default_value_t = BuiltinScene::Random)]
+    builtin: BuiltinScene,
+
+    /// Width of the image in pixels [default: 1200, or the one of the scene file]
+    #[arg(long)]
+    width: Option<u32>,
+
+    /// Height of the image in pixels [default: 800, or the one of the scene file]
+    #[arg(long)]
+    height: Option<u32>,
+
+    /// Number of samples per pixel [default: 100, or the one of the scene file]
+    #[arg(long)]
+    samples: Option<u32>,
+
+    /// Maximum number of bounces of a path [default: 50, or the one of the scene file]
+    #[arg(long)]
+    max_depth: Option<u8>,
+
+    /// Number of worker threads [default: 10, or the one of the scene file]
+    #[arg(long)]
+    threads: Option<usize>,
+
+    /// Width and height of the tiles the threads render [default: 16, or the one of the scene
+    /// file]
+    #[arg(long)]
+    tile_size: Option<u32>,
+
+    /// Order in which the tiles are rendered: scanline, spiral or hilbert [default: scanline,
+    /// or the one of the scene file]
+    #[arg(long)]
+    tile_order: Option<TileOrder>,
+
+    /// Seed of the random numbers used to render and to generate the random built-in scene
+    /// [default: 0, or the one of the scene file]
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// How the BVH splits objects: sah, midpoint or equal-count [default: sah, or the one of
+    /// the scene file]
+    #[arg(long)]
+    split_strategy: Option<SplitStrategy>,
+
+    /// How the samples are spread: independent, stratified, halton or sobol [default: sobol,
+    /// or the one of the scene file]
+    #[arg(long)]
+    sampler: Option<SamplerKind>,
+
+    /// Reconstruction filter: box, tent, gaussian, mitchell or lanczos [default: box, or the
+    /// one of the scene file]
+    #[arg(long)]
+    filter: Option<Filter>,
+
+    /// Radius of the filter in pixels [default: the one of the filter, or the one of the scene
+    /// file]
+    #[arg(long)]
+    filter_radius: Option<f32>,
+
+    /// Filter the noise out of the image, guided by the albedo and normals of the surfaces
+    /// [default: false, or the one of the scene file]
+    #[arg(long, value_name = "BOOL", num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    denoise: Option<bool>,
+
+    /// Exposure compensation in stops (EV) [default: 0, or the one of the scene file]
+    #[arg(long, allow_negative_numbers = true)]
+    exposure: Option<f32>,
+
+    /// Tone map of 8 bit images: clamp, reinhard, extended-reinhard, hable or aces [default:
+    /// clamp, or the one of the scene file]
+    #[arg(long)]
+    tone_map: Option<ToneMap>,
+
+    /// Radiance mapped to white by extended-reinhard and hable [default: 4 and 11.2, or the one
+    /// of the scene file]
+    #[arg(long)]
+    white_point: Option<f32>,
+
+    /// Stop sampling a pixel once the relative standard error of its brightness falls under
+    /// this threshold, 0.01 being a good start [default: off, or the one of the scene file]
+    #[arg(long)]
+    noise_threshold: Option<f32>,
+
+    /// Fewest samples per pixel with a noise threshold [default: 16, or the one of the scene
+    /// file]
+    #[arg(long)]
+    min_samples: Option<u32>,
 
+    /// Path of the rendered image
+    #[arg(short, long, default_value = "output/ray_traced_image.png")]
+    output: PathBuf,
+
+    /// Also save an image of the number of samples taken by every pixel
+    #[arg(long, value_name = "FILE")]
+    heatmap: Option<PathBuf>,
+
+    /// Also save what the camera rays hit first to an .exr, .pfm or .hdr file: albedo, normal,
+    /// position, depth, object-id or material-id; can be repeated
+    #[arg(long, value_name = "NAME=FILE", value_parser = parse_aov_output)]
+    aov: Vec<(Aov, PathBuf)>,
+
+    /// Render in passes of 1, 2, 4... samples per pixel and save the image after each of them
+    #[arg(long)]
+    progressive: bool,
+
+    /// Save the scene, camera and settings to a TOML scene file instead of rendering them
+    #[arg(long, value_name = "FILE")]
+    export: Option<PathBuf>,
+
+    /// Format of the rendered image, guessed from the output extension when not given; exr,
+    /// exr-half, pfm and hdr keep the linear radiance above 1
+    #[arg(short, long, value_enum)]
+    format: Option<OutputFormat>,
+}
+
+fn make_scene<R: Rng>(rng: &mut R) -> Scene {
     let mut world = Scene::new();
 
     for a in -5..5 {
@@ -28,8 +224,10 @@ fn make_scene() -> Scene {
 
             if (center - scene_center).length() > 4.0 {
                 if choose_mat < 0.5 {
-                    let color = Vec3::random_vec3(0.0, 1.0) * Vec3::random_vec3(0.0, 1.0);
-                    let material = Material::Lambert { color };
+                    let color = Vec3::random_vec3(rng, 0.0, 1.0) * Vec3::random_vec3(rng, 0.0, 1.0);
+                    let material = Material::Lambert {
+                        texture: color.into(),
+                    };
                     let sphere = Geometry::Sphere {
                         center,
                         radius: 0.2,
@@ -38,7 +236,7 @@ fn make_scene() -> Scene {
                     world.add_object(sphere);
                 }
             } else if choose_mat < 0.85 {
-                let color = Vec3::random_vec3(0.5, 1.0);
+                let color = Vec3::random_vec3(rng, 0.5, 1.0);
                 let fuzz: f32 = rng.gen_range(0.0..=0.5);
                 let material = Material::Metal { color, fuzz };
                 let sphere = Geometry::Sphere {
@@ -74,7 +272,7 @@ fn make_scene() -> Scene {
     world.add_object(sphere_1);
 
     let material_2 = Material::Lambert {
-        color: Vec3::new(0.4, 0.2, 0.1),
+        texture: Vec3::new(0.4, 0.2, 0.1).into(),
     };
     let sphere_2 = Geometry::Sphere {
         center: Vec3::new(-4.0, 1.0, 0.0),
@@ -95,7 +293,7 @@ fn make_scene() -> Scene {
     world.add_object(sphere_3);
 
     let material_ground = Material::Lambert {
-        color: Vec3::new(0.5, 0.5, 0.5),
+        texture: Vec3::new(0.5, 0.5, 0.5).into(),
     };
     let ground = Geometry::Sphere {
         center: Vec3::new(0.0, -1000.0, 0.0),
@@ -107,30 +305,404 @@ fn make_scene() -> Scene {
     world
 }
 
-fn main() {
-    let mut img: RgbImage = ImageBuffer::new(IMAGE_WIDTH, IMAGE_HEIGHT);
-
-    let look_from = Vec3::new(13.0, 2.0, 6.0);
-    let look_at = Vec3::new(0.0, 0.0, 0.0);
-    let camera = Camera::new(
-        IMAGE_ASPECT_RATIO,
-        20.0,
-        &look_from,
-        &look_at,
-        &Vec3::new(0.0, 1.0, 0.0),
-        0.1,
-        10.0,
+fn random_scene_camera() -> CameraSettings {
+    CameraSettings {
+        look_from: Vec3::new(13.0, 2.0, 6.0),
+        look_at: Vec3::new(0.0, 0.0, 0.0),
+        vup: Vec3::new(0.0, 1.0, 0.0),
+        vertical_field_of_view: 20.0,
+        aperture: 0.1,
+        focus_dist: 10.0,
+    }
+}
+
+fn make_box_mesh(minimum: Vec3, maximum: Vec3, material: Material) -> Mesh {
+    let positions = (0..8)
+        .map(|corner| {
+            Vec3::new(
+                match corner & 1 {
+                    0 => minimum.x,
+                    _ => maximum.x,
+                },
+                match corner & 2 {
+                    0 => minimum.y,
+                    _ => maximum.y,
+                },
+                match corner & 4 {
+                    0 => minimum.z,
+                    _ => maximum.z,
+                },
+            )
+        })
+        .collect();
+    let faces: [[u32; 4]; 6] = [
+        [0, 1, 3, 2],
+        [4, 6, 7, 5],
+        [0, 4, 5, 1],
+        [2, 3, 7, 6],
+        [0, 2, 6, 4],
+        [1, 5, 7, 3],
+    ];
+    let triangles = faces
+        .iter()
+        .flat_map(|face| [[face[0], face[1], face[2]], [face[0], face[2], face[3]]])
+        .collect();
+    Mesh::new(positions, None, None, triangles, vec![material], None)
+}
+
+fn add_quad(world: &mut Scene, corners: [Vec3; 4], material: &Material) {
+    for vertices in [
+        [corners[0], corners[1], corners[2]],
+        [corners[0], corners[2], corners[3]],
+    ] {
+        world.add_object(Geometry::Triangle {
+            vertices,
+            normals: None,
+            uvs: None,
+            material: material.clone(),
+        });
+    }
+}
+
+fn make_cornell_box() -> Scene {
+    let mut world = Scene::new();
+    world.background = Background::Color(Vec3::zero());
+
+    let red = Material::Lambert {
+        texture: Vec3::new(0.65, 0.05, 0.05).into(),
+    };
+    let white = Material::Lambert {
+        texture: Vec3::new(0.73, 0.73, 0.73).into(),
+    };
+    let green = Material::Lambert {
+        texture: Vec3::new(0.12, 0.45, 0.15).into(),
+    };
+    let light = Material::DiffuseLight {
+        color: Vec3::identity(),
+        intensity: 15.0,
+    };
+
+    let point = Vec3::new;
+    add_quad(
+        &mut world,
+        [
+            point(555.0, 0.0, 0.0),
+            point(555.0, 555.0, 0.0),
+            point(555.0, 555.0, 555.0),
+            point(555.0, 0.0, 555.0),
+        ],
+        &green,
     );
+    add_quad(
+        &mut world,
+        [
+            point(0.0, 0.0, 0.0),
+            point(0.0, 555.0, 0.0),
+            point(0.0, 555.0, 555.0),
+            point(0.0, 0.0, 555.0),
+        ],
+        &red,
+    );
+    add_quad(
+        &mut world,
+        [
+            point(213.0, 554.0, 227.0),
+            point(343.0, 554.0, 227.0),
+            point(343.0, 554.0, 332.0),
+            point(213.0, 554.0, 332.0),
+        ],
+        &light,
+    );
+    for y in [0.0, 555.0] {
+        add_quad(
+            &mut world,
+            [
+                point(0.0, y, 0.0),
+                point(555.0, y, 0.0),
+                point(555.0, y, 555.0),
+                point(0.0, y, 555.0),
+            ],
+            &white,
+        );
+    }
+    add_quad(
+        &mut world,
+        [
+            point(0.0, 0.0, 555.0),
+            point(555.0, 0.0, 555.0),
+            point(555.0, 555.0, 555.0),
+            point(0.0, 555.0, 555.0),
+        ],
+        &white,
+    );
+
+    // both boxes share a single mesh, placed with instances
+    let unit_box = Arc::new(Geometry::Mesh {
+        mesh: Arc::new(make_box_mesh(Vec3::zero(), Vec3::identity(), white)),
+    });
+    world.add_object(Geometry::Instance {
+        object: Arc::clone(&unit_box),
+        transform: Transform::scale(&Vec3::new(165.0, 330.0, 165.0))
+            .then(&Transform::rotate_y(15.0))
+            .then(&Transform::translate(&Vec3::new(265.0, 0.0, 295.0))),
+    });
+    world.add_object(Geometry::Instance {
+        object: unit_box,
+        transform: Transform::scale(&Vec3::new(165.0, 165.0, 165.0))
+            .then(&Transform::rotate_y(-18.0))
+            .then(&Transform::translate(&Vec3::new(130.0, 0.0, 65.0))),
+    });
+
+    world
+}
+
+fn cornell_box_camera() -> CameraSettings {
+    CameraSettings {
+        look_from: Vec3::new(278.0, 278.0, -800.0),
+        look_at: Vec3::new(278.0, 278.0, 0.0),
+        vup: Vec3::new(0.0, 1.0, 0.0),
+        vertical_field_of_view: 40.0,
+        aperture: 0.0,
+        focus_dist: 10.0,
+    }
+}
+
+// looks at the whole scene from the front and slightly above
+fn framing_camera(world: &Scene) -> Result<CameraSettings, Box<dyn Error>> {
+    let bounding_box = world
+        .bounding_box()
+        .ok_or("the scene is empty or unbounded, so a camera can't be placed")?;
+    let center = bounding_box.centroid();
+    let radius = ((bounding_box.maximum - bounding_box.minimum).length() / 2.0).max(1e-3);
+    let vertical_field_of_view: f32 = 40.0;
+    let distance = radius / (vertical_field_of_view.to_radians() / 2.0).sin();
+    let look_from = center + Vec3::new(0.0, 0.4, 1.0).unit_vector() * distance;
+
+    Ok(CameraSettings {
+        look_from,
+        look_at: center,
+        vup: Vec3::new(0.0, 1.0, 0.0),
+        vertical_field_of_view,
+        aperture: 0.0,
+        focus_dist: distance,
+    })
+}
+
+fn run(cli: Cli) -> Result<(), Box<dyn Error>> {
+    let (mut world, camera, mut settings) = match &cli.scene {
+        Some(path) if is_scene_description(path) => {
+            let description = SceneDescription::load(path)?;
+            for warning in description.warnings.iter() {
+                eprintln!("warning: {}", warning);
+            }
+            println!(
+                "loaded {} objects from {}",
+                description.scene.objects.len(),
+                path.display()
+            );
+            (description.scene, description.camera, description.settings)
+        }
+        Some(path) => {
+            let model = ObjModel::load(path)?;
+            for warning in model.warnings.iter() {
+                eprintln!("warning: {}", warning);
+            }
+            println!(
+                "loaded {} triangles from {}",
+                model.triangle_count(),
+                path.display()
+            );
+            let mut world = Scene::new();
+            let default_material = Material::Lambert {
+                texture: Vec3::new(0.8, 0.8, 0.8).into(),
+            };
+            model.add_to_scene(&mut world, default_material);
+            let camera = framing_camera(&world)?;
+            (world, camera, RenderSettings::default())
+        }
+        None => match cli.builtin {
+            BuiltinScene::Random => {
+                let seed = cli.seed.unwrap_or(RenderSettings::default().seed);
+                let mut rng = StdRng::seed_from_u64(seed);
+                (
+                    make_scene(&mut rng),
+                    random_scene_camera(),
+                    RenderSettings::default(),
+                )
+            }
+            BuiltinScene::Cornell => (
+                make_cornell_box(),
+                cornell_box_camera(),
+                RenderSettings::default(),
+            ),
+        },
+    };
+
+    // the command line takes precedence over the scene file
+    settings.width = cli.width.unwrap_or(settings.width);
+    settings.height = cli.height.unwrap_or(settings.height);
+    settings.samples_per_pixel = cli.samples.unwrap_or(settings.samples_per_pixel);
+    settings.sampler = cli.sampler.unwrap_or(settings.sampler);
+    settings.filter = cli.filter.unwrap_or(settings.filter);
+    settings.filter_radius = cli.filter_radius.or(settings.filter_radius);
+    settings.denoise = cli.denoise.unwrap_or(settings.denoise);
+    settings.exposure = cli.exposure.unwrap_or(settings.exposure);
+    settings.tone_map = cli.tone_map.unwrap_or(settings.tone_map);
+    settings.white_point = cli.white_point.or(settings.white_point);
+    settings.noise_threshold = cli.noise_threshold.or(settings.noise_threshold);
+    settings.min_samples_per_pixel = cli.min_samples.unwrap_or(settings.min_samples_per_pixel);
+    settings.max_depth = cli.max_depth.unwrap_or(settings.max_depth);
+    settings.num_threads = cli.threads.unwrap_or(settings.num_threads);
+    settings.tile_size = cli.tile_size.unwrap_or(settings.tile_size);
+    settings.tile_order = cli.tile_order.unwrap_or(settings.tile_order);
+    settings.split_strategy = cli.split_strategy.unwrap_or(settings.split_strategy);
+    settings.seed = cli.seed.unwrap_or(settings.seed);
+    settings.validate()?;
+
+    if let Some(path) = &cli.export {
+        let description = SceneDescription {
+            scene: world,
+            camera,
+            settings,
+            warnings: vec![],
+        };
+        description.save(path)?;
+        println!("saved the scene to {}", path.display());
+        return Ok(());
+    }
+
+    let output = match cli.format {
+        Some(format) => Output::from(format),
+        None => match HdrFormat::from_path(&cli.output) {
+            Some(format) => Output::Hdr(format),
+            None => Output::Image(guess_format(&cli.output)?),
+        },
+    };
+
+    let heatmap = match &cli.heatmap {
+        Some(path) => Some((path, guess_format(path)?)),
+        None => None,
+    };
+
+    let aovs = cli
+        .aov
+        .iter()
+        .map(|(aov, path)| match HdrFormat::from_path(path) {
+            Some(format) => Ok((*aov, path, format)),
+            None => Err(format!(
+                "can't save the {} AOV to '{}', expected an .exr, .pfm or .hdr file",
+                aov,
+                path.display()
+            )),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    settings.aovs = !aovs.is_empty();
 
-    let world = make_scene();
+    let camera = camera.camera(settings.aspect_ratio());
 
     let now = Instant::now();
-    ray_trace(&camera, &world, &mut img);
-    println!("ray tracing took {:.2?}", now.elapsed());
+    world.build_bvh(settings.split_strategy);
+    println!("building the BVH took {:.2?}", now.elapsed());
+    if let Some(stats) = world.bvh_stats() {
+        println!("BVH: {}", stats);
+    }
+
+    let save = |render: &Render| -> Result<(), Box<dyn Error>> {
+        match output {
+            Output::Image(format) => save_image(&render.image(&settings), &cli.output, format)?,
+            Output::Hdr(format) => {
+                create_parent_directory(&cli.output)?;
+                save_hdr(render, &cli.output, format)
+                    .map_err(|error| format!("can't save '{}': {}", cli.output.display(), error))?;
+            }
+        }
+        if let Some((path, format)) = &heatmap {
+            save_image(&render.sample_heatmap(), path, *format)?;
+        }
+        for (aov, path, format) in &aovs {
+            create_parent_directory(path)?;
+            save_aov(render, *aov, path, *format)
+                .map_err(|error| format!("can't save '{}': {}", path.display(), error))?;
+        }
+        Ok(())
+    };
+
+    let now = Instant::now();
+    match cli.progressive {
+        true => {
+            let mut result = Ok(());
+            ray_trace_progressive(&camera, &world, &settings, |render, samples| {
+                result = save(render);
+                match &result {
+                    Ok(()) => {
+                        println!(
+                            "\nsaved {} with {} samples per pixel after {:.2?}",
+                            cli.output.display(),
+                            samples,
+                            now.elapsed()
+                        );
+                        ControlFlow::Continue(())
+                    }
+                    Err(_) => ControlFlow::Break(()),
+                }
+            })?;
+            result?;
+            println!("ray tracing took {:.2?}", now.elapsed());
+        }
+        false => {
+            let render = ray_trace(&camera, &world, &settings)?;
+            println!("ray tracing took {:.2?}", now.elapsed());
+
+            save(&render)?;
+            println!("saved {}", cli.output.display());
+        }
+    }
 
-    if !Path::new("output").exists() {
-        create_dir("output").unwrap();
+    Ok(())
+}
+
+fn parse_aov_output(value: &str) -> Result<(Aov, PathBuf), String> {
+    let (name, path) = value
+        .split_once('=')
+        .ok_or_else(|| format!("expected NAME=FILE, found '{}'", value))?;
+    Ok((name.parse()?, PathBuf::from(path)))
+}
+
+fn guess_format(path: &Path) -> Result<ImageFormat, String> {
+    ImageFormat::from_path(path).map_err(|_| {
+        format!(
+            "can't guess the image format of '{}', pass --format",
+            path.display()
+        )
+    })
+}
+
+fn create_parent_directory(path: &Path) -> io::Result<()> {
+    if let Some(directory) = path.parent() {
+        if !directory.as_os_str().is_empty() && !directory.exists() {
+            create_dir_all(directory)?;
+        }
     }
+    Ok(())
+}
+
+fn save_image(img: &RgbImage, path: &Path, format: ImageFormat) -> Result<(), Box<dyn Error>> {
+    create_parent_directory(path)?;
+    img.save_with_format(path, format)
+        .map_err(|error| format!("can't save '{}': {}", path.display(), error))?;
+    Ok(())
+}
 
-    img.save("output/ray_traced_image.png").unwrap();
+fn is_scene_description(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("toml"))
+}
+
+fn main() {
+    let cli = Cli::parse();
+    if let Err(error) = run(cli) {
+        eprintln!("error: {}", error);
+        process::exit(1);
+    }
 }
@@ -1,37 +1,91 @@
-use crate::{hit_record::HitRecord, ray::Ray, vec_three::Vec3};
-use rand::Rng;
+use crate::{hit_record::HitRecord, ray::Ray, sampler::Sampler, texture::Texture, vec_three::Vec3};
+use std::f32::consts::PI;
 
-#[derive(Clone, Copy)]
+#[derive(Clone, PartialEq)]
 pub enum Material {
-    Lambert { color: Vec3 },
+    Lambert { texture: Texture },
     Metal { color: Vec3, fuzz: f32 },
     Dielectric { color: Vec3, refraction_index: f32 },
+    DiffuseLight { color: Vec3, intensity: f32 },
 }
 
 impl Material {
-    pub fn scatter(&self, ray_in: &Ray, hit_record: &HitRecord) -> Option<(Vec3, Ray)> {
-        match self {
-            &Material::Lambert { color } => {
-                Some((color, Material::_scatter_lambertian(ray_in, hit_record)))
-            }
-            &Material::Metal { color, fuzz } => {
-                match Material::_scatter_metal(ray_in, hit_record, fuzz) {
-                    Some(ray) => Some((color, ray)),
-                    _ => None,
-                }
+    pub fn scatter<S: Sampler + ?Sized>(
+        &self,
+        ray_in: &Ray,
+        hit_record: &HitRecord,
+        sampler: &mut S,
+    ) -> Option<(Vec3, Ray)> {
+        // every bounce reads the same dimensions whatever the material, so that they line up
+        // between the samples of a pixel
+        let direction_sample = sampler.next_2d();
+        let extra_sample = sampler.next_1d();
+
+        match *self {
+            Material::Lambert { ref texture } => Some((
+                texture.value(hit_record.uv, &hit_record.point),
+                Material::_scatter_lambertian(ray_in, hit_record, direction_sample),
+            )),
+            Material::Metal { color, fuzz } => {
+                Material::_scatter_metal(ray_in, hit_record, fuzz, direction_sample, extra_sample)
+                    .map(|ray| (color, ray))
             }
-            &Material::Dielectric {
+            Material::Dielectric {
                 color,
                 refraction_index,
             } => Some((
                 color,
-                Material::_scatter_dialectric(ray_in, hit_record, refraction_index),
+                Material::_scatter_dialectric(ray_in, hit_record, refraction_index, extra_sample),
             )),
+            Material::DiffuseLight { .. } => None,
+        }
+    }
+
+    /// Density with respect to solid angle of `scatter` returning `direction`.
+    ///
+    /// Materials that reflect or refract in a single direction return `None`, their scattered
+    /// rays can't be combined with sampling the lights.
+    pub fn scattering_pdf(&self, hit_record: &HitRecord, direction: &Vec3) -> Option<f32> {
+        match *self {
+            Material::Lambert { .. } => {
+                let cosine = Vec3::dot(&hit_record.normal, &direction.unit_vector());
+                Some(cosine.max(0.0) / PI)
+            }
+            _ => None,
         }
     }
 
-    fn _scatter_lambertian(_ray_in: &Ray, hit_record: &HitRecord) -> Ray {
-        let mut scattered_ray_direction = hit_record.normal + Vec3::random_unit_vector();
+    /// Light reflected towards the incoming ray for light arriving from `direction`, scaled
+    /// by the cosine with the normal. Only defined for materials with a `scattering_pdf`.
+    pub fn evaluate(&self, hit_record: &HitRecord, direction: &Vec3) -> Vec3 {
+        match *self {
+            Material::Lambert { ref texture } => {
+                let cosine = Vec3::dot(&hit_record.normal, &direction.unit_vector());
+                texture.value(hit_record.uv, &hit_record.point) * (cosine.max(0.0) / PI)
+            }
+            _ => Vec3::zero(),
+        }
+    }
+
+    /// Color of the surface at the hit point, regardless of the lighting.
+    pub fn albedo(&self, hit_record: &HitRecord) -> Vec3 {
+        match *self {
+            Material::Lambert { ref texture } => texture.value(hit_record.uv, &hit_record.point),
+            Material::Metal { color, .. }
+            | Material::Dielectric { color, .. }
+            | Material::DiffuseLight { color, .. } => color,
+        }
+    }
+
+    pub fn emitted(&self, _hit_record: &HitRecord) -> Vec3 {
+        match *self {
+            Material::DiffuseLight { color, intensity } => color * intensity,
+            _ => Vec3::zero(),
+        }
+    }
+
+    fn _scatter_lambertian(_ray_in: &Ray, hit_record: &HitRecord, sample: (f32, f32)) -> Ray {
+        let mut scattered_ray_direction = hit_record.normal + Vec3::sample_unit_vector(sample);
         if scattered_ray_direction.near_zero() {
             scattered_ray_direction = hit_record.normal;
         }
@@ -41,9 +95,15 @@ impl Material {
         }
     }
 
-    fn _scatter_metal(ray_in: &Ray, hit_record: &HitRecord, fuzz: f32) -> Option<Ray> {
+    fn _scatter_metal(
+        ray_in: &Ray,
+        hit_record: &HitRecord,
+        fuzz: f32,
+        sample: (f32, f32),
+        radius_sample: f32,
+    ) -> Option<Ray> {
         let reflected_ray = Vec3::reflect(&ray_in.direction.unit_vector(), &hit_record.normal)
-            + Vec3::random_in_unit_sphere() * fuzz;
+            + Vec3::sample_in_unit_sphere(sample, radius_sample) * fuzz;
         if Vec3::dot(&reflected_ray, &hit_record.normal) > 0.0 {
             return Some(Ray {
                 origin: hit_record.point,
@@ -53,23 +113,25 @@ impl Material {
         None
     }
 
-    fn _scatter_dialectric(ray_in: &Ray, hit_record: &HitRecord, refraction_index: f32) -> Ray {
-        let mut rng = rand::thread_rng();
-
+    fn _scatter_dialectric(
+        ray_in: &Ray,
+        hit_record: &HitRecord,
+        refraction_index: f32,
+        reflect_sample: f32,
+    ) -> Ray {
         let refraction_ratio = match hit_record.is_front_face() {
             true => 1.0 / refraction_index,
             false => refraction_index,
         };
 
-        let cos_theta =
-            (Vec3::dot(&ray_in.direction.unit_vector(), &hit_record.normal) * -1.0).min(1.0);
+        let cos_theta = (-Vec3::dot(&ray_in.direction.unit_vector(), &hit_record.normal)).min(1.0);
         let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
 
         let can_refract = refraction_ratio * sin_theta <= 1.0;
         let direction = match can_refract {
             true => {
                 let reflect_prob = Material::_shlick_approx(cos_theta, refraction_ratio);
-                match reflect_prob > rng.gen() {
+                match reflect_prob > reflect_sample {
                     true => -Vec3::reflect(&ray_in.direction.unit_vector(), &hit_record.normal),
                     false => Vec3::refract(
                         &ray_in.direction.unit_vector(),
@@ -0,0 +1,181 @@
+use std::path::PathBuf;
+
+use crate::{
+    aabb::AABB,
+    bvh::{SplitStrategy, BVH},
+    geometry::Geometry,
+    hit_record::HitRecord,
+    material::Material,
+    ray::Ray,
+    vec_three::Vec3,
+};
+
+/// The file a mesh was loaded from, which scene files refer to instead of repeating its
+/// triangles.
+#[derive(Clone, PartialEq)]
+pub struct MeshSource {
+    pub path: PathBuf,
+    /// Material of the faces that the file doesn't give one.
+    pub default_material: Material,
+}
+
+/// Indexed triangle mesh with its own bounding volume hierarchy.
+///
+/// Vertices are shared between triangles, and every triangle refers to one of the mesh
+/// materials through `material_ids`, so a model with many faces is a single scene object.
+#[derive(Clone)]
+pub struct Mesh {
+    positions: Vec<Vec3>,
+    normals: Option<Vec<Vec3>>,
+    uvs: Option<Vec<(f32, f32)>>,
+    triangles: Vec<[u32; 3]>,
+    materials: Vec<Material>,
+    material_ids: Option<Vec<u32>>,
+    bvh: BVH,
+    split_strategy: SplitStrategy,
+    source: Option<MeshSource>,
+}
+
+impl Mesh {
+    /// Create a new Mesh.
+    ///
+    /// `normals` and `uvs` are per vertex and `material_ids` has one entry per triangle.
+    /// Without `material_ids` every triangle uses the first material. The hierarchy is built
+    /// with `SplitStrategy::SAH`, `Scene::build_bvh` rebuilds it with the strategy of the scene.
+    ///
+    /// # Panics
+    ///
+    /// The `new` function will panic if an index is out of range or if the per vertex
+    /// attributes don't have one entry for every position.
+    pub fn new(
+        positions: Vec<Vec3>,
+        normals: Option<Vec<Vec3>>,
+        uvs: Option<Vec<(f32, f32)>>,
+        triangles: Vec<[u32; 3]>,
+        materials: Vec<Material>,
+        material_ids: Option<Vec<u32>>,
+    ) -> Mesh {
+        assert!(!materials.is_empty());
+        assert!(normals.as_ref().is_none_or(|n| n.len() == positions.len()));
+        assert!(uvs.as_ref().is_none_or(|uvs| uvs.len() == positions.len()));
+        assert!(triangles
+            .iter()
+            .all(|triangle| triangle.iter().all(|&i| (i as usize) < positions.len())));
+        if let Some(material_ids) = &material_ids {
+            assert_eq!(material_ids.len(), triangles.len());
+            assert!(material_ids.iter().all(|&i| (i as usize) < materials.len()));
+        }
+
+        let split_strategy = SplitStrategy::SAH;
+        let bvh = build_bvh(&positions, &triangles, split_strategy);
+
+        Mesh {
+            positions,
+            normals,
+            uvs,
+            triangles,
+            materials,
+            material_ids,
+            bvh,
+            split_strategy,
+            source: None,
+        }
+    }
+
+    /// Records the file the mesh was loaded from.
+    pub fn with_source(self, source: MeshSource) -> Mesh {
+        Mesh {
+            source: Some(source),
+            ..self
+        }
+    }
+
+    pub fn source(&self) -> Option<&MeshSource> {
+        self.source.as_ref()
+    }
+
+    /// Rebuilds the bounding volume hierarchy of the triangles with `strategy`.
+    pub fn build_bvh(&mut self, strategy: SplitStrategy) {
+        self.bvh = build_bvh(&self.positions, &self.triangles, strategy);
+        self.split_strategy = strategy;
+    }
+
+    pub fn split_strategy(&self) -> SplitStrategy {
+        self.split_strategy
+    }
+
+    pub fn triangle_count(&self) -> usize {
+        self.triangles.len()
+    }
+
+    pub fn vertex_count(&self) -> usize {
+        self.positions.len()
+    }
+
+    pub fn positions(&self) -> &[Vec3] {
+        &self.positions
+    }
+
+    pub fn normals(&self) -> Option<&[Vec3]> {
+        self.normals.as_deref()
+    }
+
+    pub fn uvs(&self) -> Option<&[(f32, f32)]> {
+        self.uvs.as_deref()
+    }
+
+    pub fn triangles(&self) -> &[[u32; 3]] {
+        &self.triangles
+    }
+
+    pub fn materials(&self) -> &[Material] {
+        &self.materials
+    }
+
+    pub fn material_ids(&self) -> Option<&[u32]> {
+        self.material_ids.as_deref()
+    }
+
+    pub fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<(HitRecord, &Material)> {
+        self.bvh.hit(ray, t_min, t_max, |index, t_min, t_max| {
+            let triangle = &self.triangles[index];
+            let vertices = triangle.map(|i| self.positions[i as usize]);
+            let normals = self
+                .normals
+                .as_ref()
+                .map(|normals| triangle.map(|i| normals[i as usize]));
+            let uvs = self
+                .uvs
+                .as_ref()
+                .map(|uvs| triangle.map(|i| uvs[i as usize]));
+            Geometry::hit_triangle(ray, t_min, t_max, &vertices, &normals, &uvs).map(
+                |mut hit_record| {
+                    hit_record.material_index = self.material_index(index);
+                    (
+                        hit_record,
+                        &self.materials[hit_record.material_index as usize],
+                    )
+                },
+            )
+        })
+    }
+
+    pub fn bounding_box(&self) -> Option<AABB> {
+        self.bvh.bounding_box()
+    }
+
+    fn material_index(&self, triangle_index: usize) -> u32 {
+        match &self.material_ids {
+            Some(material_ids) => material_ids[triangle_index],
+            None => 0,
+        }
+    }
+}
+
+fn build_bvh(positions: &[Vec3], triangles: &[[u32; 3]], strategy: SplitStrategy) -> BVH {
+    let bounding_boxes: Vec<Option<AABB>> = triangles
+        .iter()
+        .map(|triangle| Geometry::bounding_box_triangle(&triangle.map(|i| positions[i as usize])))
+        .collect();
+    BVH::new(&bounding_boxes, strategy)
+}
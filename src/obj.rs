@@ -0,0 +1,676 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::{
+    geometry::Geometry,
+    material::Material,
+    mesh::{Mesh, MeshSource},
+    scene::Scene,
+    vec_three::Vec3,
+};
+
+const DEFAULT_REFRACTION_INDEX: f32 = 1.5;
+
+#[derive(Debug)]
+pub enum ObjError {
+    Io {
+        path: PathBuf,
+        error: io::Error,
+    },
+    Parse {
+        path: PathBuf,
+        line: usize,
+        message: String,
+    },
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ObjError::Io { path, error } => write!(f, "{}: {}", path.display(), error),
+            ObjError::Parse {
+                path,
+                line,
+                message,
+            } => write!(f, "{}:{}: {}", path.display(), line, message),
+        }
+    }
+}
+
+impl std::error::Error for ObjError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ObjError::Io { error, .. } => Some(error),
+            ObjError::Parse { .. } => None,
+        }
+    }
+}
+
+/// Indices of the position, texture coordinate and normal of a face corner.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ObjVertex {
+    pub position: usize,
+    pub uv: Option<usize>,
+    pub normal: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ObjTriangle {
+    pub vertices: [ObjVertex; 3],
+    /// Index into `ObjModel::materials`, `None` when the face was declared before any `usemtl`.
+    pub material: Option<usize>,
+}
+
+/// Faces between two `g` or `o` statements of an OBJ file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ObjGroup {
+    /// Names of the statement joined by spaces, `default` for the faces before the first one.
+    pub name: String,
+    /// Range of `ObjModel::triangles` made of the faces of the group.
+    pub triangles: Range<usize>,
+}
+
+/// Contents of a Wavefront OBJ file along with the materials of its MTL libraries.
+///
+/// Faces only refer to the vertex data by index. The groups and objects only name ranges of the
+/// triangles, the whole file becomes a single mesh.
+#[derive(Clone)]
+pub struct ObjModel {
+    pub path: PathBuf,
+    pub positions: Vec<Vec3>,
+    pub normals: Vec<Vec3>,
+    pub uvs: Vec<(f32, f32)>,
+    pub materials: Vec<(String, Material)>,
+    pub triangles: Vec<ObjTriangle>,
+    /// Groups that have faces, in the order of the file. A group that is started again later
+    /// in the file appears once for every part of it.
+    pub groups: Vec<ObjGroup>,
+    /// Problems that didn't stop the file from loading, such as a missing MTL library whose
+    /// faces fell back to the default material.
+    pub warnings: Vec<String>,
+}
+
+impl ObjModel {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<ObjModel, ObjError> {
+        let path = path.as_ref();
+        let contents = read_file(path)?;
+        let mut parser = Parser::new(path);
+
+        let mut model = ObjModel {
+            path: path.to_path_buf(),
+            positions: vec![],
+            normals: vec![],
+            uvs: vec![],
+            materials: vec![],
+            triangles: vec![],
+            groups: vec![],
+            warnings: vec![],
+        };
+        let mut material_indices: HashMap<String, usize> = HashMap::new();
+        let mut current_material: Option<usize> = None;
+        // the materials of a missing library are unknown rather than wrong
+        let mut missing_library = false;
+        let mut current_group = String::from("default");
+        let mut group_start = 0;
+
+        for (line_index, line) in contents.lines().enumerate() {
+            parser.line = line_index + 1;
+            let mut tokens = line.split_whitespace();
+            let keyword = match tokens.next() {
+                Some(keyword) if !keyword.starts_with('#') => keyword,
+                _ => continue,
+            };
+            let arguments: Vec<&str> = tokens.collect();
+
+            match keyword {
+                "v" => {
+                    let values = parser.floats(&arguments, 3, 4)?;
+                    model
+                        .positions
+                        .push(Vec3::new(values[0], values[1], values[2]));
+                }
+                "vn" => {
+                    let values = parser.floats(&arguments, 3, 3)?;
+                    model
+                        .normals
+                        .push(Vec3::new(values[0], values[1], values[2]));
+                }
+                "vt" => {
+                    let values = parser.floats(&arguments, 1, 3)?;
+                    model
+                        .uvs
+                        .push((values[0], values.get(1).copied().unwrap_or(0.0)));
+                }
+                "f" => {
+                    if arguments.len() < 3 {
+                        return Err(parser.error(format!(
+                            "a face needs at least 3 vertices, found {}",
+                            arguments.len()
+                        )));
+                    }
+                    let corners = arguments
+                        .iter()
+                        .map(|argument| parser.face_vertex(argument, &model))
+                        .collect::<Result<Vec<_>, _>>()?;
+                    // triangulate the polygon as a fan around its first corner
+                    for i in 1..corners.len() - 1 {
+                        model.triangles.push(ObjTriangle {
+                            vertices: [corners[0], corners[i], corners[i + 1]],
+                            material: current_material,
+                        });
+                    }
+                }
+                "mtllib" => {
+                    if arguments.is_empty() {
+                        return Err(parser.error(String::from("mtllib needs a file name")));
+                    }
+                    let directory = path.parent().unwrap_or_else(|| Path::new(""));
+                    // file names may contain spaces, so try the whole remainder of the line first
+                    let library = directory.join(arguments.join(" "));
+                    let libraries = match library.exists() {
+                        true => vec![library],
+                        false => arguments.iter().map(|name| directory.join(name)).collect(),
+                    };
+                    for library in libraries {
+                        let library_materials = match load_mtl(&library) {
+                            Ok(library_materials) => library_materials,
+                            Err(ObjError::Io { error, .. }) => {
+                                model.warnings.push(format!(
+                                    "{}:{}: can't read '{}': {}, its materials are replaced by \
+                                     the default one",
+                                    path.display(),
+                                    parser.line,
+                                    library.display(),
+                                    error
+                                ));
+                                missing_library = true;
+                                continue;
+                            }
+                            Err(error) => return Err(error),
+                        };
+                        for (name, material) in library_materials {
+                            match material_indices.get(&name) {
+                                Some(&index) => model.materials[index].1 = material,
+                                None => {
+                                    material_indices.insert(name.clone(), model.materials.len());
+                                    model.materials.push((name, material));
+                                }
+                            }
+                        }
+                    }
+                }
+                "usemtl" => {
+                    let name = arguments.join(" ");
+                    current_material = match material_indices.get(&name) {
+                        Some(&index) => Some(index),
+                        None if missing_library => None,
+                        None => return Err(parser.error(format!("unknown material '{}'", name))),
+                    };
+                }
+                "g" | "o" => {
+                    model.close_group(current_group, group_start);
+                    current_group = arguments.join(" ");
+                    group_start = model.triangles.len();
+                }
+                // smoothing groups, free-form geometry, lines and points don't affect triangles
+                _ => {}
+            }
+        }
+        model.close_group(current_group, group_start);
+
+        Ok(model)
+    }
+
+    // keeps the group that started at triangle `start` unless it doesn't have any faces
+    fn close_group(&mut self, name: String, start: usize) {
+        if start < self.triangles.len() {
+            self.groups.push(ObjGroup {
+                name,
+                triangles: start..self.triangles.len(),
+            });
+        }
+    }
+
+    pub fn triangle_count(&self) -> usize {
+        self.triangles.len()
+    }
+
+    /// Builds a single indexed mesh out of all the faces. Faces without a material use
+    /// `default_material`. The mesh remembers the file it was loaded from.
+    pub fn to_mesh(&self, default_material: Material) -> Mesh {
+        // OBJ corners index positions, uvs and normals separately, while the mesh needs a
+        // single index per vertex, so every distinct combination becomes one mesh vertex
+        let mut vertex_indices: HashMap<ObjVertex, u32> = HashMap::new();
+        let mut vertices: Vec<ObjVertex> = vec![];
+        let mut triangles = Vec::with_capacity(self.triangle_count());
+        let mut material_ids = Vec::with_capacity(self.triangle_count());
+        for triangle in self.triangles.iter() {
+            triangles.push(triangle.vertices.map(|vertex| {
+                *vertex_indices.entry(vertex).or_insert_with(|| {
+                    vertices.push(vertex);
+                    (vertices.len() - 1) as u32
+                })
+            }));
+            material_ids.push(match triangle.material {
+                Some(index) => index as u32,
+                None => self.materials.len() as u32,
+            });
+        }
+
+        let positions = vertices
+            .iter()
+            .map(|vertex| self.positions[vertex.position])
+            .collect();
+        let normals = vertices
+            .iter()
+            .map(|vertex| vertex.normal.map(|normal| self.normals[normal]))
+            .collect();
+        let uvs = vertices
+            .iter()
+            .map(|vertex| vertex.uv.map(|uv| self.uvs[uv]))
+            .collect();
+        let mut materials: Vec<Material> = self
+            .materials
+            .iter()
+            .map(|(_, material)| material.clone())
+            .collect();
+        materials.push(default_material.clone());
+
+        Mesh::new(
+            positions,
+            normals,
+            uvs,
+            triangles,
+            materials,
+            Some(material_ids),
+        )
+        .with_source(MeshSource {
+            path: self.path.clone(),
+            default_material,
+        })
+    }
+
+    /// Adds the whole model to the scene as one mesh object.
+    pub fn add_to_scene(&self, scene: &mut Scene, default_material: Material) {
+        if self.triangle_count() == 0 {
+            return;
+        }
+        scene.add_object(Geometry::Mesh {
+            mesh: Arc::new(self.to_mesh(default_material)),
+        });
+    }
+}
+
+/// Reads the materials of a Wavefront MTL file, in the order they are declared.
+pub fn load_mtl<P: AsRef<Path>>(path: P) -> Result<Vec<(String, Material)>, ObjError> {
+    let path = path.as_ref();
+    let contents = read_file(path)?;
+    let mut parser = Parser::new(path);
+
+    let mut materials = vec![];
+    let mut current: Option<(String, MtlProperties)> = None;
+
+    for (line_index, line) in contents.lines().enumerate() {
+        parser.line = line_index + 1;
+        let mut tokens = line.split_whitespace();
+        let keyword = match tokens.next() {
+            Some(keyword) if !keyword.starts_with('#') => keyword,
+            _ => continue,
+        };
+        let arguments: Vec<&str> = tokens.collect();
+
+        if keyword == "newmtl" {
+            if arguments.is_empty() {
+                return Err(parser.error(String::from("newmtl needs a material name")));
+            }
+            if let Some((name, properties)) = current.take() {
+                materials.push((name, properties.to_material()));
+            }
+            current = Some((arguments.join(" "), MtlProperties::default()));
+            continue;
+        }
+
+        let properties = match current.as_mut() {
+            Some((_, properties)) => properties,
+            None => {
+                return Err(
+                    parser.error(format!("'{}' appears before any newmtl statement", keyword))
+                )
+            }
+        };
+        match keyword {
+            "Kd" => properties.diffuse = parser.color(&arguments)?,
+            "Ks" => properties.specular = parser.color(&arguments)?,
+            "Ke" => properties.emission = parser.color(&arguments)?,
+            "Tf" => properties.transmission = Some(parser.color(&arguments)?),
+            "Ns" => properties.shininess = parser.floats(&arguments, 1, 1)?[0],
+            "Ni" => properties.refraction_index = Some(parser.floats(&arguments, 1, 1)?[0]),
+            "d" => properties.dissolve = parser.floats(&arguments, 1, 1)?[0],
+            "Tr" => properties.dissolve = 1.0 - parser.floats(&arguments, 1, 1)?[0],
+            "illum" => {
+                properties.illumination = match arguments.first().map(|a| a.parse::<u8>()) {
+                    Some(Ok(illumination)) if arguments.len() == 1 => illumination,
+                    _ => {
+                        return Err(parser.error(format!(
+                            "expected an illumination model number, found '{}'",
+                            arguments.join(" ")
+                        )))
+                    }
+                }
+            }
+            // ambient color and texture maps aren't supported by the renderer
+            _ => {}
+        }
+    }
+
+    if let Some((name, properties)) = current.take() {
+        materials.push((name, properties.to_material()));
+    }
+
+    Ok(materials)
+}
+
+struct MtlProperties {
+    diffuse: Vec3,
+    specular: Vec3,
+    emission: Vec3,
+    transmission: Option<Vec3>,
+    shininess: f32,
+    refraction_index: Option<f32>,
+    dissolve: f32,
+    illumination: u8,
+}
+
+impl Default for MtlProperties {
+    fn default() -> Self {
+        MtlProperties {
+            diffuse: Vec3::new(0.8, 0.8, 0.8),
+            specular: Vec3::zero(),
+            emission: Vec3::zero(),
+            transmission: None,
+            shininess: 0.0,
+            refraction_index: None,
+            dissolve: 1.0,
+            illumination: 2,
+        }
+    }
+}
+
+impl MtlProperties {
+    fn to_material(&self) -> Material {
+        let transparent = self.dissolve < 1.0 || matches!(self.illumination, 4 | 6 | 7 | 9);
+        let reflective = matches!(self.illumination, 3 | 5 | 8)
+            || (self.diffuse.near_zero() && !self.specular.near_zero());
+
+        if !self.emission.near_zero() {
+            Material::DiffuseLight {
+                color: self.emission,
+                intensity: 1.0,
+            }
+        } else if transparent {
+            Material::Dielectric {
+                color: self.transmission.unwrap_or_else(Vec3::identity),
+                refraction_index: self.refraction_index.unwrap_or(DEFAULT_REFRACTION_INDEX),
+            }
+        } else if reflective {
+            // map the phong exponent to a roughness, a high exponent gives a sharp reflection
+            let fuzz = (2.0 / (self.shininess.max(0.0) + 2.0)).sqrt();
+            let color = match self.specular.near_zero() {
+                true => self.diffuse,
+                false => self.specular,
+            };
+            Material::Metal { color, fuzz }
+        } else {
+            Material::Lambert {
+                texture: self.diffuse.into(),
+            }
+        }
+    }
+}
+
+struct Parser<'a> {
+    path: &'a Path,
+    line: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn new(path: &'a Path) -> Self {
+        Parser { path, line: 0 }
+    }
+
+    fn error(&self, message: String) -> ObjError {
+        ObjError::Parse {
+            path: self.path.to_path_buf(),
+            line: self.line,
+            message,
+        }
+    }
+
+    fn floats(&self, arguments: &[&str], min: usize, max: usize) -> Result<Vec<f32>, ObjError> {
+        if arguments.len() < min || arguments.len() > max {
+            let expected = match min == max {
+                true => format!("{}", min),
+                false => format!("{} to {}", min, max),
+            };
+            return Err(self.error(format!(
+                "expected {} numbers, found {}",
+                expected,
+                arguments.len()
+            )));
+        }
+
+        arguments
+            .iter()
+            .map(|argument| {
+                argument
+                    .parse::<f32>()
+                    .map_err(|_| self.error(format!("'{}' is not a number", argument)))
+            })
+            .collect()
+    }
+
+    fn color(&self, arguments: &[&str]) -> Result<Vec3, ObjError> {
+        // a single value is used for all three channels
+        let values = self.floats(arguments, 1, 3)?;
+        match values.len() {
+            1 => Ok(Vec3::new(values[0], values[0], values[0])),
+            3 => Ok(Vec3::new(values[0], values[1], values[2])),
+            _ => Err(self.error(String::from("expected 1 or 3 color components, found 2"))),
+        }
+    }
+
+    fn face_vertex(&self, argument: &str, model: &ObjModel) -> Result<ObjVertex, ObjError> {
+        let mut parts = argument.split('/');
+        let position = match parts.next() {
+            Some(part) if !part.is_empty() => {
+                self.index(part, model.positions.len(), "position")?
+            }
+            _ => {
+                return Err(self.error(format!("face vertex '{}' has no position index", argument)))
+            }
+        };
+        let uv = match parts.next() {
+            Some(part) if !part.is_empty() => {
+                Some(self.index(part, model.uvs.len(), "texture coordinate")?)
+            }
+            _ => None,
+        };
+        let normal = match parts.next() {
+            Some(part) if !part.is_empty() => {
+                Some(self.index(part, model.normals.len(), "normal")?)
+            }
+            _ => None,
+        };
+        if parts.next().is_some() {
+            return Err(self.error(format!("malformed face vertex '{}'", argument)));
+        }
+
+        Ok(ObjVertex {
+            position,
+            uv,
+            normal,
+        })
+    }
+
+    // OBJ indices start at 1, negative ones count back from the last element read so far
+    fn index(&self, value: &str, count: usize, kind: &str) -> Result<usize, ObjError> {
+        let index: i64 = value
+            .parse()
+            .map_err(|_| self.error(format!("'{}' is not a valid {} index", value, kind)))?;
+        let resolved = match index {
+            0 => None,
+            index if index > 0 => Some(index - 1),
+            index => Some(count as i64 + index),
+        };
+        match resolved {
+            Some(resolved) if resolved >= 0 && (resolved as usize) < count => Ok(resolved as usize),
+            _ => Err(self.error(format!(
+                "{} index {} is out of range, {} defined so far",
+                kind, index, count
+            ))),
+        }
+    }
+}
+
+fn read_file(path: &Path) -> Result<String, ObjError> {
+    fs::read_to_string(path).map_err(|error| ObjError::Io {
+        path: path.to_path_buf(),
+        error,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // writes `files` to a directory of their own and returns its path
+    fn write_files(test: &str, files: &[(&str, &str)]) -> PathBuf {
+        let directory = std::env::temp_dir().join(format!("ray_tracing_obj_{}", test));
+        fs::create_dir_all(&directory).unwrap();
+        for (name, contents) in files {
+            fs::write(directory.join(name), contents).unwrap();
+        }
+        directory
+    }
+
+    const SQUARE: &str = "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\n";
+
+    #[test]
+    fn reports_obj_errors_with_file_and_line() {
+        let source = format!("{}f 1 2 3\nf 1 2 5\n", SQUARE);
+        let directory = write_files("obj_error", &[("model.obj", &source)]);
+        let path = directory.join("model.obj");
+
+        let error = ObjModel::load(&path).err().unwrap();
+        assert!(matches!(error, ObjError::Parse { line: 6, .. }));
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "{}:6: position index 5 is out of range, 4 defined so far",
+                path.display()
+            )
+        );
+    }
+
+    #[test]
+    fn reports_mtl_errors_with_file_and_line() {
+        let source = format!("mtllib model.mtl\n{}f 1 2 3\n", SQUARE);
+        let directory = write_files(
+            "mtl_error",
+            &[
+                ("model.obj", &source),
+                ("model.mtl", "newmtl red\nKd 1 0 0\nNs shiny\n"),
+            ],
+        );
+
+        let error = ObjModel::load(directory.join("model.obj")).err().unwrap();
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "{}:3: 'shiny' is not a number",
+                directory.join("model.mtl").display()
+            )
+        );
+    }
+
+    #[test]
+    fn falls_back_to_the_default_material_without_the_library() {
+        let source = format!("mtllib missing.mtl\n{}usemtl red\nf 1 2 3 4\n", SQUARE);
+        let directory = write_files("missing_mtl", &[("model.obj", &source)]);
+
+        let model = ObjModel::load(directory.join("model.obj")).unwrap();
+        assert_eq!(model.warnings.len(), 1);
+        assert!(model.warnings[0].contains("model.obj:1: can't read"));
+        assert_eq!(model.triangle_count(), 2);
+        assert!(model
+            .triangles
+            .iter()
+            .all(|triangle| triangle.material.is_none()));
+    }
+
+    #[test]
+    fn rejects_unknown_materials_of_loaded_libraries() {
+        let source = format!("mtllib model.mtl\n{}usemtl blue\nf 1 2 3\n", SQUARE);
+        let directory = write_files(
+            "unknown_material",
+            &[
+                ("model.obj", &source),
+                ("model.mtl", "newmtl red\nKd 1 0 0\n"),
+            ],
+        );
+
+        let error = ObjModel::load(directory.join("model.obj")).err().unwrap();
+        assert!(matches!(error, ObjError::Parse { line: 6, .. }));
+    }
+
+    #[test]
+    fn keeps_the_faces_of_every_group() {
+        let source = format!("{}g first\nf 1 2 3\no second\nf 1 3 4\n", SQUARE);
+        let directory = write_files("groups", &[("model.obj", &source)]);
+
+        let model = ObjModel::load(directory.join("model.obj")).unwrap();
+        assert_eq!(model.triangle_count(), 2);
+        let mesh = model.to_mesh(Material::Lambert {
+            texture: Vec3::zero().into(),
+        });
+        assert_eq!(mesh.triangle_count(), 2);
+    }
+
+    #[test]
+    fn names_the_triangles_of_every_group() {
+        let source = format!(
+            "{}f 1 2 3
+g first
+f 1 2 3 4
+o empty
+g second part
+f 1 3 4
+g first
+f 2 3 4
+",
+            SQUARE
+        );
+        let directory = write_files("group_names", &[("model.obj", &source)]);
+
+        let model = ObjModel::load(directory.join("model.obj")).unwrap();
+        let groups: Vec<(&str, Range<usize>)> = model
+            .groups
+            .iter()
+            .map(|group| (group.name.as_str(), group.triangles.clone()))
+            .collect();
+        assert_eq!(
+            groups,
+            [
+                ("default", 0..1),
+                ("first", 1..3),
+                ("second part", 3..4),
+                ("first", 4..5),
+            ]
+        );
+    }
+}
@@ -0,0 +1,127 @@
+use image::{Rgb, RgbImage};
+
+use crate::{aov::Aovs, image::write_color, settings::RenderSettings, vec_three::Vec3};
+
+/// Result of `ray_trace`: the linear color of every pixel, the number of samples it took and
+/// its output variables, stored row by row from the top left.
+#[derive(Clone)]
+pub struct Render {
+    width: u32,
+    height: u32,
+    colors: Vec<Vec3>,
+    samples: Vec<u32>,
+    aovs: Vec<Aovs>,
+}
+
+impl Render {
+    /// Create a new Render from the average color, the number of samples and the output
+    /// variables of every pixel.
+    ///
+    /// # Panics
+    ///
+    /// The `new` function will panic if the number of pixels doesn't match the size.
+    pub fn new(
+        width: u32,
+        height: u32,
+        colors: Vec<Vec3>,
+        samples: Vec<u32>,
+        aovs: Vec<Aovs>,
+    ) -> Render {
+        let size = width as usize * height as usize;
+        assert_eq!(colors.len(), size);
+        assert_eq!(samples.len(), size);
+        assert_eq!(aovs.len(), size);
+
+        Render {
+            width,
+            height,
+            colors,
+            samples,
+            aovs,
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn color(&self, x: u32, y: u32) -> Vec3 {
+        self.colors[self.index(x, y)]
+    }
+
+    pub fn samples(&self, x: u32, y: u32) -> u32 {
+        self.samples[self.index(x, y)]
+    }
+
+    /// What the camera rays of the pixel hit first.
+    pub fn aovs(&self, x: u32, y: u32) -> &Aovs {
+        &self.aovs[self.index(x, y)]
+    }
+
+    /// Image ready to be displayed, exposed and tone mapped as the settings say.
+    pub fn image(&self, settings: &RenderSettings) -> RgbImage {
+        let exposure = settings.exposure.exp2();
+        let white_point = settings
+            .white_point
+            .unwrap_or_else(|| settings.tone_map.default_white_point());
+
+        let mut img = RgbImage::new(self.width, self.height);
+        for (index, &color) in self.colors.iter().enumerate() {
+            let (x, y) = self.position(index);
+            let color = settings.tone_map.apply(color * exposure, white_point);
+            write_color(&mut img, x, y, &color);
+        }
+        img
+    }
+
+    /// Number of samples taken by every pixel, from black for none through blue, red and
+    /// yellow to white for the most samples taken by any pixel.
+    pub fn sample_heatmap(&self) -> RgbImage {
+        let max_samples = self.samples.iter().copied().max().unwrap_or(0).max(1);
+
+        let mut img = RgbImage::new(self.width, self.height);
+        for (index, &samples) in self.samples.iter().enumerate() {
+            let (x, y) = self.position(index);
+            let color = heat(samples as f32 / max_samples as f32);
+            img.put_pixel(
+                x,
+                y,
+                Rgb([
+                    (255.0 * color.x) as u8,
+                    (255.0 * color.y) as u8,
+                    (255.0 * color.z) as u8,
+                ]),
+            );
+        }
+        img
+    }
+
+    fn index(&self, x: u32, y: u32) -> usize {
+        assert!(x < self.width && y < self.height);
+        (y * self.width + x) as usize
+    }
+
+    fn position(&self, index: usize) -> (u32, u32) {
+        (index as u32 % self.width, index as u32 / self.width)
+    }
+}
+
+// color ramp over [0, 1], linearly interpolated between evenly spaced stops
+fn heat(value: f32) -> Vec3 {
+    let stops = [
+        Vec3::zero(),
+        Vec3::new(0.0, 0.0, 1.0),
+        Vec3::new(1.0, 0.0, 0.0),
+        Vec3::new(1.0, 1.0, 0.0),
+        Vec3::identity(),
+    ];
+
+    let position = value.clamp(0.0, 1.0) * (stops.len() - 1) as f32;
+    let stop = (position as usize).min(stops.len() - 2);
+    let t = position - stop as f32;
+    stops[stop] * (1.0 - t) + stops[stop + 1] * t
+}
@@ -0,0 +1,463 @@
+use std::fmt;
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+use rand::{Rng, SeedableRng};
+use rand_pcg::Pcg32;
+
+// Halton dimensions past the number of primes in the table fall back to hashed random numbers
+const HALTON_DIMENSIONS: usize = 256;
+// largest f32 below 1, the samples must stay in [0, 1)
+const ONE_MINUS_EPSILON: f32 = 1.0 - f32::EPSILON / 2.0;
+
+/// Source of the numbers in [0, 1) that pick the positions in the pixel, on the lens and the
+/// directions of the bounces of a sample.
+///
+/// Every sample of a pixel reads its numbers one dimension after the other, the same
+/// dimension of all the samples of a pixel being spread evenly over [0, 1) by the samplers
+/// other than `Independent`.
+pub trait Sampler {
+    /// Starts the dimensions of `sample` of the pixel at (`x`, `y`) over.
+    fn start_sample(&mut self, x: u32, y: u32, sample: u32);
+
+    fn next_1d(&mut self) -> f32;
+
+    fn next_2d(&mut self) -> (f32, f32);
+}
+
+/// Samplers that can be picked for a render.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SamplerKind {
+    /// Unrelated random numbers for every sample.
+    Independent,
+    /// Jittered samples, one per stratum of [0, 1) or [0, 1)², in a random order for every
+    /// dimension.
+    Stratified,
+    /// Halton sequence with a different prime for every dimension, Owen-scrambled per pixel.
+    Halton,
+    /// Pairs of the first two dimensions of the Sobol sequence, Owen-scrambled per pixel and
+    /// dimension.
+    #[default]
+    Sobol,
+}
+
+impl SamplerKind {
+    /// Sampler for renders with `seed` and up to `samples_per_pixel` samples per pixel.
+    pub fn sampler(&self, seed: u64, samples_per_pixel: u32) -> Box<dyn Sampler> {
+        match self {
+            SamplerKind::Independent => Box::new(IndependentSampler::new(seed)),
+            SamplerKind::Stratified => Box::new(StratifiedSampler::new(seed, samples_per_pixel)),
+            SamplerKind::Halton => Box::new(HaltonSampler::new(seed)),
+            SamplerKind::Sobol => Box::new(SobolSampler::new(seed)),
+        }
+    }
+}
+
+impl fmt::Display for SamplerKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            SamplerKind::Independent => "independent",
+            SamplerKind::Stratified => "stratified",
+            SamplerKind::Halton => "halton",
+            SamplerKind::Sobol => "sobol",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for SamplerKind {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_lowercase().as_str() {
+            "independent" => Ok(SamplerKind::Independent),
+            "stratified" => Ok(SamplerKind::Stratified),
+            "halton" => Ok(SamplerKind::Halton),
+            "sobol" => Ok(SamplerKind::Sobol),
+            _ => Err(format!(
+                "unknown sampler '{}', expected one of independent, stratified, halton, sobol",
+                value
+            )),
+        }
+    }
+}
+
+pub struct IndependentSampler {
+    seed: u64,
+    rng: Pcg32,
+}
+
+impl IndependentSampler {
+    pub fn new(seed: u64) -> IndependentSampler {
+        IndependentSampler {
+            seed,
+            rng: Pcg32::seed_from_u64(seed),
+        }
+    }
+}
+
+impl Sampler for IndependentSampler {
+    fn start_sample(&mut self, x: u32, y: u32, sample: u32) {
+        // every sample of every pixel gets its own generator, so the image doesn't depend on
+        // which thread renders which pixel or in which order
+        self.rng = Pcg32::seed_from_u64(mix(pixel_seed(self.seed, x, y) ^ sample as u64));
+    }
+
+    fn next_1d(&mut self) -> f32 {
+        self.rng.gen()
+    }
+
+    fn next_2d(&mut self) -> (f32, f32) {
+        (self.rng.gen(), self.rng.gen())
+    }
+}
+
+/// Correlated multi-jittered sampling (Kensler 2013), which stays stratified for any number of
+/// samples per pixel.
+pub struct StratifiedSampler {
+    seed: u64,
+    samples_per_pixel: u32,
+    pixel_seed: u64,
+    sample: u32,
+    dimension: u32,
+}
+
+impl StratifiedSampler {
+    /// # Panics
+    ///
+    /// The `new` function will panic if `samples_per_pixel` is zero.
+    pub fn new(seed: u64, samples_per_pixel: u32) -> StratifiedSampler {
+        assert!(samples_per_pixel > 0);
+
+        StratifiedSampler {
+            seed,
+            samples_per_pixel,
+            pixel_seed: 0,
+            sample: 0,
+            dimension: 0,
+        }
+    }
+
+    fn dimension_seed(&mut self) -> u32 {
+        self.dimension += 1;
+        mix(self.pixel_seed ^ self.dimension as u64) as u32
+    }
+}
+
+impl Sampler for StratifiedSampler {
+    fn start_sample(&mut self, x: u32, y: u32, sample: u32) {
+        self.pixel_seed = pixel_seed(self.seed, x, y);
+        self.sample = sample;
+        self.dimension = 0;
+    }
+
+    fn next_1d(&mut self) -> f32 {
+        let count = self.samples_per_pixel;
+        let seed = self.dimension_seed();
+        let stratum = permute(self.sample % count, count, seed);
+        let jitter = random_float(self.sample, seed.wrapping_mul(0xa399_d265));
+        ((stratum as f32 + jitter) / count as f32).min(ONE_MINUS_EPSILON)
+    }
+
+    fn next_2d(&mut self) -> (f32, f32) {
+        let count = self.samples_per_pixel;
+        let seed = self.dimension_seed();
+        // as close to a square grid as the number of samples allows
+        let columns = ((count as f32).sqrt() as u32).max(1);
+        let rows = count.div_ceil(columns);
+
+        let sample = permute(self.sample % count, count, seed.wrapping_mul(0x5163_3e2d));
+        let column = sample % columns;
+        let row = sample / columns;
+        let sx = permute(column, columns, seed.wrapping_mul(0xa511_e9b3));
+        let sy = permute(row, rows, seed.wrapping_mul(0x63d8_3595));
+        let jx = random_float(sample, seed.wrapping_mul(0xa399_d265));
+        let jy = random_float(sample, seed.wrapping_mul(0x711a_d6a5));
+
+        (
+            ((column as f32 + (sy as f32 + jx) / rows as f32) / columns as f32)
+                .min(ONE_MINUS_EPSILON),
+            ((row as f32 + (sx as f32 + jy) / columns as f32) / rows as f32).min(ONE_MINUS_EPSILON),
+        )
+    }
+}
+
+pub struct HaltonSampler {
+    seed: u64,
+    pixel_seed: u64,
+    sample: u32,
+    dimension: usize,
+}
+
+impl HaltonSampler {
+    pub fn new(seed: u64) -> HaltonSampler {
+        HaltonSampler {
+            seed,
+            pixel_seed: 0,
+            sample: 0,
+            dimension: 0,
+        }
+    }
+}
+
+impl Sampler for HaltonSampler {
+    fn start_sample(&mut self, x: u32, y: u32, sample: u32) {
+        self.pixel_seed = pixel_seed(self.seed, x, y);
+        self.sample = sample;
+        self.dimension = 0;
+    }
+
+    fn next_1d(&mut self) -> f32 {
+        let dimension = self.dimension;
+        self.dimension += 1;
+
+        let seed = mix(self.pixel_seed ^ dimension as u64);
+        match primes().get(dimension) {
+            Some(&base) => scrambled_radical_inverse(base, self.sample, seed),
+            None => random_float(self.sample, seed as u32),
+        }
+    }
+
+    fn next_2d(&mut self) -> (f32, f32) {
+        (self.next_1d(), self.next_1d())
+    }
+}
+
+/// Padded Sobol sampler: every pair of dimensions is a (0, 2)-sequence with its own Owen
+/// scrambling and its own shuffled order of the samples (Burley 2020).
+pub struct SobolSampler {
+    seed: u64,
+    pixel_seed: u64,
+    sample: u32,
+    dimension: u32,
+}
+
+impl SobolSampler {
+    pub fn new(seed: u64) -> SobolSampler {
+        SobolSampler {
+            seed,
+            pixel_seed: 0,
+            sample: 0,
+            dimension: 0,
+        }
+    }
+
+    // seeds of the shuffle and of the scrambling of each of the two coordinates
+    fn dimension_seeds(&mut self) -> [u32; 3] {
+        self.dimension += 1;
+        let seed = mix(self.pixel_seed ^ self.dimension as u64);
+        [seed as u32, (seed >> 32) as u32, mix(seed) as u32]
+    }
+}
+
+impl Sampler for SobolSampler {
+    fn start_sample(&mut self, x: u32, y: u32, sample: u32) {
+        self.pixel_seed = pixel_seed(self.seed, x, y);
+        self.sample = sample;
+        self.dimension = 0;
+    }
+
+    fn next_1d(&mut self) -> f32 {
+        let [shuffle, scramble, _] = self.dimension_seeds();
+        let index = nested_uniform_scramble(self.sample, shuffle);
+        to_float(nested_uniform_scramble(index.reverse_bits(), scramble))
+    }
+
+    fn next_2d(&mut self) -> (f32, f32) {
+        let [shuffle, scramble_x, scramble_y] = self.dimension_seeds();
+        let index = nested_uniform_scramble(self.sample, shuffle);
+        (
+            to_float(nested_uniform_scramble(index.reverse_bits(), scramble_x)),
+            to_float(nested_uniform_scramble(
+                sobol_second_dimension(index),
+                scramble_y,
+            )),
+        )
+    }
+}
+
+fn pixel_seed(seed: u64, x: u32, y: u32) -> u64 {
+    let pixel = ((x as u64) << 32) | y as u64;
+    mix(seed ^ mix(pixel))
+}
+
+// SplitMix64 finalizer, spreads nearby integers over all the bits
+fn mix(value: u64) -> u64 {
+    let mut value = value.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    value = (value ^ (value >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    value = (value ^ (value >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    value ^ (value >> 31)
+}
+
+// fixed point number with 32 bits after the point to [0, 1)
+fn to_float(value: u32) -> f32 {
+    (value as f32 / 4_294_967_296.0).min(ONE_MINUS_EPSILON)
+}
+
+// random permutation of [0, length) picked by `seed`, from Kensler's "Correlated Multi-Jittered
+// Sampling": a bijection over the next power of two, repeated until it lands in range
+fn permute(mut index: u32, length: u32, seed: u32) -> u32 {
+    let mut mask = length.wrapping_sub(1);
+    mask |= mask >> 1;
+    mask |= mask >> 2;
+    mask |= mask >> 4;
+    mask |= mask >> 8;
+    mask |= mask >> 16;
+
+    loop {
+        index ^= seed;
+        index = index.wrapping_mul(0xe170_893d);
+        index ^= seed >> 16;
+        index ^= (index & mask) >> 4;
+        index ^= seed >> 8;
+        index = index.wrapping_mul(0x0929_eb3f);
+        index ^= seed >> 23;
+        index ^= (index & mask) >> 1;
+        index = index.wrapping_mul(1 | seed >> 27);
+        index = index.wrapping_mul(0x6935_fa69);
+        index ^= (index & mask) >> 11;
+        index = index.wrapping_mul(0x74dc_b303);
+        index ^= (index & mask) >> 2;
+        index = index.wrapping_mul(0x9e50_1cc3);
+        index ^= (index & mask) >> 2;
+        index = index.wrapping_mul(0xc860_a3df);
+        index &= mask;
+        index ^= index >> 5;
+        if index < length {
+            break;
+        }
+    }
+    index.wrapping_add(seed) % length
+}
+
+// hashes `index` and `seed` to [0, 1)
+fn random_float(index: u32, seed: u32) -> f32 {
+    to_float(mix(((seed as u64) << 32) | index as u64) as u32)
+}
+
+// second dimension of the Sobol sequence, the first one being the bit reversed index
+fn sobol_second_dimension(mut index: u32) -> u32 {
+    let mut result = 0;
+    let mut direction = 1 << 31;
+    while index != 0 {
+        if index & 1 != 0 {
+            result ^= direction;
+        }
+        index >>= 1;
+        direction ^= direction >> 1;
+    }
+    result
+}
+
+// Owen scrambling of a fixed point number in [0, 1): flips each bit depending on the bits above
+// it only, through the Laine-Karras hash of the reversed bits
+fn nested_uniform_scramble(value: u32, seed: u32) -> u32 {
+    let mut value = value.reverse_bits().wrapping_add(seed);
+    value ^= value.wrapping_mul(0x6c50_b47c);
+    value ^= value.wrapping_mul(0xb82f_1e52);
+    value ^= value.wrapping_mul(0xc7af_e638);
+    value ^= value.wrapping_mul(0x8d22_f6e6);
+    value.reverse_bits()
+}
+
+// radical inverse of `index` in `base` with every digit permuted depending on the digits before
+// it, which is an Owen scrambling for any base
+fn scrambled_radical_inverse(base: u32, mut index: u32, seed: u64) -> f32 {
+    let inverse_base = 1.0 / base as f64;
+    let mut digit_weight = inverse_base;
+    let mut prefix = seed;
+    let mut result = 0.0;
+    // the digits after the last one of the index are zeros, but scrambled they still count
+    while digit_weight > f32::EPSILON as f64 / 4.0 {
+        let digit = index % base;
+        index /= base;
+
+        let scrambled = permute(digit, base, mix(prefix) as u32);
+        result += scrambled as f64 * digit_weight;
+        prefix = mix(prefix ^ digit as u64);
+        digit_weight *= inverse_base;
+    }
+    (result as f32).min(ONE_MINUS_EPSILON)
+}
+
+fn primes() -> &'static [u32] {
+    static PRIMES: OnceLock<Vec<u32>> = OnceLock::new();
+    PRIMES.get_or_init(|| {
+        let mut primes = Vec::with_capacity(HALTON_DIMENSIONS);
+        let mut candidate = 2;
+        while primes.len() < HALTON_DIMENSIONS {
+            if primes
+                .iter()
+                .take_while(|&&prime| prime * prime <= candidate)
+                .all(|&prime| candidate % prime != 0)
+            {
+                primes.push(candidate);
+            }
+            candidate += 1;
+        }
+        primes
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the points of the 2D dimension pair that follows `skipped` pairs, for the first `count`
+    // samples of a pixel
+    fn points(kind: SamplerKind, seed: u64, count: u32, skipped: usize) -> Vec<(f32, f32)> {
+        let mut sampler = kind.sampler(seed, count);
+        (0..count)
+            .map(|sample| {
+                sampler.start_sample(3, 7, sample);
+                for _ in 0..skipped {
+                    sampler.next_2d();
+                }
+                sampler.next_2d()
+            })
+            .collect()
+    }
+
+    // whether every cell of a `columns` x `rows` grid over [0, 1)² holds exactly one point
+    fn one_per_cell(points: &[(f32, f32)], columns: u32, rows: u32) -> bool {
+        let mut counts = vec![0; (columns * rows) as usize];
+        for &(x, y) in points {
+            assert!((0.0..1.0).contains(&x) && (0.0..1.0).contains(&y));
+            let column = (x * columns as f32) as u32;
+            let row = (y * rows as f32) as u32;
+            counts[(row * columns + column) as usize] += 1;
+        }
+        counts.iter().all(|&count| count == 1)
+    }
+
+    #[test]
+    fn sobol_points_are_stratified() {
+        for seed in [0, 7] {
+            for skipped in [0, 2] {
+                let points = points(SamplerKind::Sobol, seed, 16, skipped);
+                // every elementary interval of a (0, 4, 2)-net
+                for (columns, rows) in [(1, 16), (2, 8), (4, 4), (8, 2), (16, 1)] {
+                    assert!(
+                        one_per_cell(&points, columns, rows),
+                        "seed {}, pair {}, {}x{} grid",
+                        seed,
+                        skipped,
+                        columns,
+                        rows
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn halton_points_are_stratified() {
+        for seed in [0, 7] {
+            // bases 2 and 3, then 5 and 7
+            for (skipped, columns, rows) in [(0, 4, 9), (1, 5, 7)] {
+                let points = points(SamplerKind::Halton, seed, columns * rows, skipped);
+                assert!(one_per_cell(&points, columns, rows), "seed {}", seed);
+            }
+        }
+    }
+}
@@ -1,33 +1,151 @@
+use std::collections::HashMap;
+
 use crate::aabb::AABB;
+use crate::background::Background;
+use crate::bvh::{BVHStats, SplitStrategy, BVH};
+use crate::environment::EnvironmentMap;
 use crate::geometry::Geometry;
 use crate::hit_record::HitRecord;
 use crate::{material::Material, ray::Ray};
 
+#[derive(Clone, Default)]
 pub struct Scene {
     pub objects: Vec<Geometry>,
+    pub background: Background,
+    // ids of the materials of every object, in the order of `Geometry::materials`
+    material_ids: Vec<Vec<u32>>,
+    material_count: u32,
+    bvh: Option<BVH>,
 }
 
 impl Scene {
     pub fn new() -> Self {
-        Scene { objects: vec![] }
+        Scene {
+            objects: vec![],
+            background: Background::default(),
+            material_ids: vec![],
+            material_count: 0,
+            bvh: None,
+        }
     }
 
+    /// Adds an object whose materials get ids of their own.
     pub fn add_object(&mut self, object: Geometry) {
+        let first_id = self.material_count + 1;
+        let material_ids = (first_id..).take(object.materials().len()).collect();
+        self.add_object_with_material_ids(object, material_ids);
+    }
+
+    /// Adds an object whose materials have the given ids, so that objects sharing a material
+    /// can share its id. Ids start at 1 and the ones of later `add_object` calls follow the
+    /// largest one.
+    ///
+    /// # Panics
+    ///
+    /// The `add_object_with_material_ids` function will panic if there isn't one id for every
+    /// material of the object or if an id is 0.
+    pub fn add_object_with_material_ids(&mut self, object: Geometry, material_ids: Vec<u32>) {
+        assert_eq!(material_ids.len(), object.materials().len());
+        assert!(material_ids.iter().all(|&id| id > 0));
+
+        self.material_count = material_ids
+            .iter()
+            .copied()
+            .fold(self.material_count, u32::max);
         self.objects.push(object);
+        self.material_ids.push(material_ids);
+        self.bvh = None;
     }
 
     pub fn clear(&mut self) {
         self.objects.clear();
+        self.material_ids.clear();
+        self.material_count = 0;
+        self.bvh = None;
+    }
+
+    /// Ids of the materials of `objects[object]` in the order of `Geometry::materials`, none
+    /// for the objects that weren't added through `add_object`.
+    pub fn material_ids(&self, object: usize) -> &[u32] {
+        self.material_ids
+            .get(object)
+            .map_or(&[], |material_ids| material_ids.as_slice())
+    }
+
+    /// Id of the material of `objects[object]` that `hit_record` hit, 0 for the objects that
+    /// weren't added through `add_object`.
+    pub fn material_id(&self, object: usize, hit_record: &HitRecord) -> u32 {
+        self.material_ids
+            .get(object)
+            .and_then(|material_ids| material_ids.get(hit_record.material_index as usize))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Builds the bounding volume hierarchy used by `hit`.
+    ///
+    /// The hierarchy is dropped whenever an object is added through `add_object`, so it has
+    /// to be rebuilt once the scene is complete. Modifying `objects` directly requires calling
+    /// this again as well. The hierarchies of the meshes are rebuilt with `strategy` too.
+    pub fn build_bvh(&mut self, strategy: SplitStrategy) {
+        let mut rebuilt_meshes = HashMap::new();
+        for object in &mut self.objects {
+            object.rebuild_mesh_bvhs(strategy, &mut rebuilt_meshes);
+        }
+
+        let bounding_boxes: Vec<Option<AABB>> = self
+            .objects
+            .iter()
+            .map(|object| object.bounding_box())
+            .collect();
+        self.bvh = Some(BVH::new(&bounding_boxes, strategy));
+    }
+
+    pub fn has_bvh(&self) -> bool {
+        self.bvh.is_some()
+    }
+
+    pub fn bvh_stats(&self) -> Option<BVHStats> {
+        self.bvh.as_ref().map(|bvh| bvh.stats())
+    }
+
+    /// The environment map lighting the scene, if the background is one.
+    pub fn environment(&self) -> Option<&EnvironmentMap> {
+        match &self.background {
+            Background::Environment(environment) => Some(environment),
+            _ => None,
+        }
     }
 
     pub fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<(HitRecord, &Material)> {
-        let mut hit_record: Option<(HitRecord, &Material)> = None;
+        self.hit_object(ray, t_min, t_max)
+            .map(|(_, hit_record, material)| (hit_record, material))
+    }
+
+    /// Same as `hit`, also returning the index in `objects` of the object hit.
+    pub fn hit_object(
+        &self,
+        ray: &Ray,
+        t_min: f32,
+        t_max: f32,
+    ) -> Option<(usize, HitRecord, &Material)> {
+        if let Some(bvh) = &self.bvh {
+            return bvh
+                .hit(ray, t_min, t_max, |index, t_min, t_max| {
+                    self.objects[index]
+                        .hit(ray, t_min, t_max)
+                        .map(|(hit_record, material)| (hit_record, (index, material)))
+                })
+                .map(|(hit_record, (index, material))| (index, hit_record, material));
+        }
+
+        let mut hit_record: Option<(usize, HitRecord, &Material)> = None;
         let mut closest_so_far = t_max;
 
-        for object in self.objects.iter() {
+        for (index, object) in self.objects.iter().enumerate() {
             if let Some((temp_hit_record, material)) = object.hit(ray, t_min, closest_so_far) {
                 closest_so_far = temp_hit_record.t;
-                hit_record = Some((temp_hit_record, &material));
+                hit_record = Some((index, temp_hit_record, material));
             };
         }
 
@@ -0,0 +1,2057 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt;
+use std::fs;
+use std::hash::Hash;
+use std::io;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use toml::de::{DeTable, DeValue};
+use toml::Spanned;
+
+use crate::{
+    background::Background,
+    camera::CameraSettings,
+    environment::EnvironmentMap,
+    geometry::Geometry,
+    material::Material,
+    mesh::Mesh,
+    obj::ObjModel,
+    scene::Scene,
+    settings::RenderSettings,
+    texture::{ImageTexture, Texture},
+    transform::{Matrix4, Transform},
+    vec_three::Vec3,
+};
+
+const DEFAULT_MESH_COLOR: f32 = 0.8;
+
+type Value<'i> = Spanned<DeValue<'i>>;
+// mesh object along with the ids of its materials in the scene
+type SceneMesh = (Arc<Geometry>, Vec<u32>);
+
+#[derive(Debug)]
+pub enum SceneFileError {
+    Io {
+        path: PathBuf,
+        error: io::Error,
+    },
+    Parse {
+        path: PathBuf,
+        line: usize,
+        message: String,
+    },
+    /// The scene uses something that can't be written to a scene file.
+    Export(String),
+}
+
+impl fmt::Display for SceneFileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SceneFileError::Io { path, error } => write!(f, "{}: {}", path.display(), error),
+            SceneFileError::Parse {
+                path,
+                line,
+                message,
+            } => write!(f, "{}:{}: {}", path.display(), line, message),
+            SceneFileError::Export(message) => write!(f, "can't export the scene: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for SceneFileError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SceneFileError::Io { error, .. } => Some(error),
+            SceneFileError::Parse { .. } | SceneFileError::Export(_) => None,
+        }
+    }
+}
+
+/// Everything needed to render an image, as read from a TOML scene file.
+///
+/// A scene file has a `[camera]` table and optional `[settings]`, `[background]`,
+/// `[textures.<name>]`, `[materials.<name>]`, `[meshes.<name>]`, `[[objects]]` and `[[lights]]`
+/// tables. Objects, meshes and materials refer to materials, meshes and textures by name, and
+/// relative paths are resolved from the directory of the scene file. See `scenes/` for examples.
+#[derive(Clone)]
+pub struct SceneDescription {
+    pub scene: Scene,
+    pub camera: CameraSettings,
+    pub settings: RenderSettings,
+    /// Problems that didn't stop the file from loading, such as the missing MTL libraries of
+    /// meshes. They aren't saved.
+    pub warnings: Vec<String>,
+}
+
+impl SceneDescription {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<SceneDescription, SceneFileError> {
+        let path = path.as_ref();
+        let source = fs::read_to_string(path).map_err(|error| SceneFileError::Io {
+            path: path.to_path_buf(),
+            error,
+        })?;
+        SceneDescription::parse(&source, path)
+    }
+
+    /// Writes a scene file that `load` reads back into the same scene, camera and settings.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), SceneFileError> {
+        let path = path.as_ref();
+        let directory = match path.parent() {
+            Some(directory) if !directory.as_os_str().is_empty() => directory,
+            _ => Path::new("."),
+        };
+        let contents = self.to_toml(directory)?;
+        fs::write(path, contents).map_err(|error| SceneFileError::Io {
+            path: path.to_path_buf(),
+            error,
+        })
+    }
+
+    /// Formats the description as a scene file stored in `directory`, which is used to make the
+    /// paths of meshes and images relative when they are inside it.
+    ///
+    /// Materials, textures and meshes are given generated names, and every object ends up in
+    /// `[[objects]]`, including lights. Materials are named after their id in the scene and
+    /// keep it when the file is loaded, as long as the ids follow each other. Backgrounds computed by a closure and images that weren't
+    /// loaded from a file can't be exported.
+    pub fn to_toml(&self, directory: &Path) -> Result<String, SceneFileError> {
+        let directory = fs::canonicalize(directory).map_err(|error| SceneFileError::Io {
+            path: directory.to_path_buf(),
+            error,
+        })?;
+        let mut writer = Writer::new(&directory);
+
+        let mut root = toml::Table::new();
+        root.insert(
+            String::from("settings"),
+            toml::Value::Table(writer.settings(&self.settings)),
+        );
+        root.insert(
+            String::from("camera"),
+            toml::Value::Table(writer.camera(&self.camera)),
+        );
+        root.insert(
+            String::from("background"),
+            toml::Value::Table(writer.background(&self.scene.background)?),
+        );
+        // the materials of the objects that were pushed without ids get ids after the others
+        let mut next_material_id = (0..self.scene.objects.len())
+            .flat_map(|object| self.scene.material_ids(object).iter().copied())
+            .max()
+            .unwrap_or(0)
+            + 1;
+        let mut objects = vec![];
+        for (index, object) in self.scene.objects.iter().enumerate() {
+            let mut material_ids = self.scene.material_ids(index).to_vec();
+            if material_ids.is_empty() {
+                let count = object.materials().len() as u32;
+                material_ids = (next_material_id..next_material_id + count).collect();
+                next_material_id += count;
+            }
+            objects.push(toml::Value::Table(writer.object(
+                object,
+                None,
+                &material_ids,
+            )?));
+        }
+        // materials are defined in the order of their ids, which numbers them the same way
+        // when the file is loaded
+        writer.materials.definitions.sort_by_key(|&(id, _)| id);
+        for (key, table) in [
+            ("textures", writer.textures.into_table()),
+            ("materials", writer.materials.into_table()),
+            ("meshes", writer.meshes.into_table()),
+        ] {
+            if !table.is_empty() {
+                root.insert(String::from(key), toml::Value::Table(table));
+            }
+        }
+        root.insert(String::from("objects"), toml::Value::Array(objects));
+
+        toml::to_string(&root).map_err(|error| SceneFileError::Export(error.to_string()))
+    }
+
+    /// Parses the contents of a scene file, `path` is used for error messages and to resolve
+    /// the paths of meshes and images.
+    pub fn parse(source: &str, path: &Path) -> Result<SceneDescription, SceneFileError> {
+        let mut parser = Parser::new(source, path);
+        let document = DeTable::parse(source).map_err(|error| {
+            parser.error(error.span().unwrap_or(0..0), error.message().to_string())
+        })?;
+        let root = Table {
+            entries: document.get_ref(),
+            span: 0..0,
+            kind: "scene",
+        };
+        parser.check_keys(
+            &root,
+            &[
+                "settings",
+                "camera",
+                "background",
+                "textures",
+                "materials",
+                "meshes",
+                "objects",
+                "lights",
+            ],
+        )?;
+
+        let settings = match root.get("settings") {
+            Some(value) => parser.settings(value)?,
+            None => RenderSettings::default(),
+        };
+        let camera = parser.camera(parser.required(&root, "camera")?)?;
+
+        let mut scene = Scene::new();
+        if let Some(value) = root.get("background") {
+            scene.background = parser.background(value)?;
+        }
+        if let Some(value) = root.get("textures") {
+            let textures = parser.table(value, "textures")?;
+            for (name, value) in textures.entries.iter() {
+                let texture = parser.texture(value, name.get_ref())?;
+                parser.textures.insert(name.get_ref().to_string(), texture);
+            }
+        }
+        if let Some(value) = root.get("materials") {
+            let materials = parser.table(value, "materials")?;
+            for (name, value) in materials.entries.iter() {
+                let material = parser.material(value, name.get_ref())?;
+                let material_id = parser.new_material_id();
+                parser
+                    .materials
+                    .insert(name.get_ref().to_string(), (material, material_id));
+            }
+        }
+        if let Some(value) = root.get("meshes") {
+            let meshes = parser.table(value, "meshes")?;
+            for (name, value) in meshes.entries.iter() {
+                let mesh = parser.mesh_definition(value, name.get_ref())?;
+                parser.meshes.insert(name.get_ref().to_string(), mesh);
+            }
+        }
+        if let Some(value) = root.get("objects") {
+            for object in parser.array(value, "objects")? {
+                let (object, material_ids) = parser.object(object)?;
+                scene.add_object_with_material_ids(object, material_ids);
+            }
+        }
+        if let Some(value) = root.get("lights") {
+            for light in parser.array(value, "lights")? {
+                let material_id = parser.new_material_id();
+                for object in parser.light(light)? {
+                    scene.add_object_with_material_ids(object, vec![material_id]);
+                }
+            }
+        }
+
+        Ok(SceneDescription {
+            scene,
+            camera,
+            settings,
+            warnings: parser.warnings,
+        })
+    }
+}
+
+struct Table<'a, 'i> {
+    entries: &'a DeTable<'i>,
+    span: Range<usize>,
+    /// What the table describes, used in error messages.
+    kind: &'a str,
+}
+
+impl<'a, 'i> Table<'a, 'i> {
+    fn get(&self, key: &str) -> Option<&'a Value<'i>> {
+        self.entries.get(key)
+    }
+}
+
+struct Parser<'s> {
+    source: &'s str,
+    path: &'s Path,
+    textures: HashMap<String, Texture>,
+    // the materials and meshes along with the ids of their materials in the scene, the named
+    // materials coming first in the order they are defined
+    materials: HashMap<String, (Material, u32)>,
+    meshes: HashMap<String, SceneMesh>,
+    // meshes used by several objects are only loaded once
+    mesh_files: HashMap<(PathBuf, Option<String>), SceneMesh>,
+    // ids of the materials of the MTL files by OBJ file and material name
+    mtl_material_ids: HashMap<(PathBuf, String), u32>,
+    material_count: u32,
+    warnings: Vec<String>,
+}
+
+impl<'s> Parser<'s> {
+    fn new(source: &'s str, path: &'s Path) -> Parser<'s> {
+        Parser {
+            source,
+            path,
+            textures: HashMap::new(),
+            materials: HashMap::new(),
+            meshes: HashMap::new(),
+            mesh_files: HashMap::new(),
+            mtl_material_ids: HashMap::new(),
+            material_count: 0,
+            warnings: vec![],
+        }
+    }
+
+    fn new_material_id(&mut self) -> u32 {
+        self.material_count += 1;
+        self.material_count
+    }
+
+    fn error(&self, span: Range<usize>, message: String) -> SceneFileError {
+        let offset = span.start.min(self.source.len());
+        SceneFileError::Parse {
+            path: self.path.to_path_buf(),
+            line: self.source[..offset].matches('\n').count() + 1,
+            message,
+        }
+    }
+
+    fn settings(&self, value: &Value) -> Result<RenderSettings, SceneFileError> {
+        let table = self.table(value, "settings")?;
+        self.check_keys(
+            &table,
+            &[
+                "width",
+                "height",
+                "samples_per_pixel",
+                "sampler",
+                "filter",
+                "filter_radius",
+                "denoise",
+                "exposure",
+                "tone_map",
+                "white_point",
+                "noise_threshold",
+                "min_samples_per_pixel",
+                "max_depth",
+                "tile_size",
+                "tile_order",
+                "threads",
+                "t_min",
+                "t_max",
+                "split_strategy",
+                "seed",
+            ],
+        )?;
+
+        let mut settings = RenderSettings::default();
+        if let Some(value) = table.get("width") {
+            settings.width = self.integer(value)?;
+        }
+        if let Some(value) = table.get("height") {
+            settings.height = self.integer(value)?;
+        }
+        if let Some(value) = table.get("samples_per_pixel") {
+            settings.samples_per_pixel = self.integer(value)?;
+        }
+        if let Some(value) = table.get("sampler") {
+            settings.sampler = self
+                .string(value)?
+                .parse()
+                .map_err(|message| self.error(value.span(), message))?;
+        }
+        if let Some(value) = table.get("filter") {
+            settings.filter = self
+                .string(value)?
+                .parse()
+                .map_err(|message| self.error(value.span(), message))?;
+        }
+        if let Some(value) = table.get("filter_radius") {
+            settings.filter_radius = Some(self.float(value)?);
+        }
+        if let Some(value) = table.get("denoise") {
+            settings.denoise = self.boolean(value)?;
+        }
+        if let Some(value) = table.get("exposure") {
+            settings.exposure = self.float(value)?;
+        }
+        if let Some(value) = table.get("tone_map") {
+            settings.tone_map = self
+                .string(value)?
+                .parse()
+                .map_err(|message| self.error(value.span(), message))?;
+        }
+        if let Some(value) = table.get("white_point") {
+            settings.white_point = Some(self.float(value)?);
+        }
+        if let Some(value) = table.get("noise_threshold") {
+            settings.noise_threshold = Some(self.float(value)?);
+        }
+        if let Some(value) = table.get("min_samples_per_pixel") {
+            settings.min_samples_per_pixel = self.integer(value)?;
+        }
+        if let Some(value) = table.get("max_depth") {
+            settings.max_depth = self.integer(value)?;
+        }
+        if let Some(value) = table.get("tile_size") {
+            settings.tile_size = self.integer(value)?;
+        }
+        if let Some(value) = table.get("tile_order") {
+            settings.tile_order = self
+                .string(value)?
+                .parse()
+                .map_err(|message| self.error(value.span(), message))?;
+        }
+        if let Some(value) = table.get("threads") {
+            settings.num_threads = self.integer(value)?;
+        }
+        if let Some(value) = table.get("t_min") {
+            settings.t_min = self.float(value)?;
+        }
+        if let Some(value) = table.get("t_max") {
+            settings.t_max = self.float(value)?;
+        }
+        if let Some(value) = table.get("split_strategy") {
+            settings.split_strategy = self
+                .string(value)?
+                .parse()
+                .map_err(|message| self.error(value.span(), message))?;
+        }
+
+        if let Some(value) = table.get("seed") {
+            // seeds above the largest TOML integer are written as negative numbers
+            settings.seed = self.integer::<i64>(value)? as u64;
+        }
+
+        settings
+            .validate()
+            .map_err(|error| self.error(value.span(), error.to_string()))?;
+        Ok(settings)
+    }
+
+    fn camera(&self, value: &Value) -> Result<CameraSettings, SceneFileError> {
+        let table = self.table(value, "camera")?;
+        self.check_keys(
+            &table,
+            &[
+                "look_from",
+                "look_at",
+                "up",
+                "vertical_fov",
+                "aperture",
+                "focus_distance",
+            ],
+        )?;
+
+        let look_from = self.vec3(self.required(&table, "look_from")?)?;
+        let look_at_value = self.required(&table, "look_at")?;
+        let look_at = self.vec3(look_at_value)?;
+        if (look_from - look_at).near_zero() {
+            return Err(self.error(
+                look_at_value.span(),
+                String::from("look_at must be different from look_from"),
+            ));
+        }
+        let vup = match table.get("up") {
+            Some(value) => {
+                let vup = self.vec3(value)?;
+                if Vec3::cross(&vup, &(look_from - look_at)).near_zero() {
+                    return Err(self.error(
+                        value.span(),
+                        String::from("up must not be parallel to the viewing direction"),
+                    ));
+                }
+                vup
+            }
+            None => Vec3::new(0.0, 1.0, 0.0),
+        };
+        let vertical_fov_value = self.required(&table, "vertical_fov")?;
+        let vertical_field_of_view = self.float(vertical_fov_value)?;
+        if !(vertical_field_of_view > 0.0 && vertical_field_of_view < 180.0) {
+            return Err(self.error(
+                vertical_fov_value.span(),
+                format!(
+                    "vertical_fov must be between 0 and 180 degrees, got {}",
+                    vertical_field_of_view
+                ),
+            ));
+        }
+        let aperture = match table.get("aperture") {
+            Some(value) => self.non_negative(value)?,
+            None => 0.0,
+        };
+        let focus_dist = match table.get("focus_distance") {
+            Some(value) => self.positive(value)?,
+            None => (look_from - look_at).length(),
+        };
+
+        Ok(CameraSettings {
+            look_from,
+            look_at,
+            vup,
+            vertical_field_of_view,
+            aperture,
+            focus_dist,
+        })
+    }
+
+    fn background(&self, value: &Value) -> Result<Background, SceneFileError> {
+        let table = self.table(value, "background")?;
+        let kind_value = self.required(&table, "type")?;
+        let table = Table {
+            kind: self.string(kind_value)?,
+            ..table
+        };
+
+        match table.kind {
+            "color" => {
+                self.check_keys(&table, &["type", "color"])?;
+                Ok(Background::Color(
+                    self.color(self.required(&table, "color")?)?,
+                ))
+            }
+            "gradient" => {
+                self.check_keys(&table, &["type", "bottom", "top", "up"])?;
+                let (default_bottom, default_top, default_up) = match Background::default() {
+                    Background::Gradient { bottom, top, up } => (bottom, top, up),
+                    _ => unreachable!(),
+                };
+                Ok(Background::Gradient {
+                    bottom: match table.get("bottom") {
+                        Some(value) => self.color(value)?,
+                        None => default_bottom,
+                    },
+                    top: match table.get("top") {
+                        Some(value) => self.color(value)?,
+                        None => default_top,
+                    },
+                    up: match table.get("up") {
+                        Some(value) => self.direction(value)?,
+                        None => default_up,
+                    },
+                })
+            }
+            "environment" => {
+                self.check_keys(&table, &["type", "path", "rotation", "intensity"])?;
+                let path_value = self.required(&table, "path")?;
+                let path = self.path(path_value)?;
+                let mut environment = EnvironmentMap::load(&path)
+                    .map_err(|error| self.load_error(path_value, &path, &error))?;
+                if let Some(value) = table.get("rotation") {
+                    environment = environment.with_rotation(self.float(value)?);
+                }
+                if let Some(value) = table.get("intensity") {
+                    environment = environment.with_intensity(self.non_negative(value)?);
+                }
+                Ok(Background::Environment(Arc::new(environment)))
+            }
+            kind => Err(self.error(
+                kind_value.span(),
+                format!(
+                    "unknown background type '{}', expected color, gradient or environment",
+                    kind
+                ),
+            )),
+        }
+    }
+
+    fn texture(&self, value: &Value, name: &str) -> Result<Texture, SceneFileError> {
+        let table = self.table(value, name)?;
+        let kind_value = self.required(&table, "type")?;
+
+        match self.string(kind_value)? {
+            "solid" => {
+                self.check_keys(&table, &["type", "color"])?;
+                Ok(Texture::Solid(self.color(self.required(&table, "color")?)?))
+            }
+            "checker" => {
+                self.check_keys(&table, &["type", "even", "odd", "scale"])?;
+                Ok(Texture::Checker {
+                    even: self.color(self.required(&table, "even")?)?,
+                    odd: self.color(self.required(&table, "odd")?)?,
+                    scale: match table.get("scale") {
+                        Some(value) => self.positive(value)?,
+                        None => 1.0,
+                    },
+                })
+            }
+            "image" => {
+                self.check_keys(&table, &["type", "path"])?;
+                let path_value = self.required(&table, "path")?;
+                let path = self.path(path_value)?;
+                let image = ImageTexture::load(&path)
+                    .map_err(|error| self.load_error(path_value, &path, &error))?;
+                Ok(Texture::Image(Arc::new(image)))
+            }
+            kind => Err(self.error(
+                kind_value.span(),
+                format!(
+                    "unknown texture type '{}', expected solid, checker or image",
+                    kind
+                ),
+            )),
+        }
+    }
+
+    fn material(&self, value: &Value, name: &str) -> Result<Material, SceneFileError> {
+        let table = self.table(value, name)?;
+        let kind_value = self.required(&table, "type")?;
+
+        match self.string(kind_value)? {
+            "lambert" => {
+                self.check_keys(&table, &["type", "color", "texture"])?;
+                let texture = match (table.get("color"), table.get("texture")) {
+                    (Some(value), None) => Texture::Solid(self.color(value)?),
+                    (None, Some(value)) => self.texture_reference(value)?,
+                    (Some(_), Some(value)) => {
+                        return Err(self.error(
+                            value.span(),
+                            String::from("a lambert material has either a color or a texture"),
+                        ))
+                    }
+                    (None, None) => {
+                        return Err(self.error(
+                            table.span,
+                            format!("missing key 'color' or 'texture' in {}", name),
+                        ))
+                    }
+                };
+                Ok(Material::Lambert { texture })
+            }
+            "metal" => {
+                self.check_keys(&table, &["type", "color", "fuzz"])?;
+                let fuzz = match table.get("fuzz") {
+                    Some(value) => {
+                        let fuzz = self.float(value)?;
+                        if !(0.0..=1.0).contains(&fuzz) {
+                            return Err(self.error(
+                                value.span(),
+                                format!("fuzz must be between 0 and 1, got {}", fuzz),
+                            ));
+                        }
+                        fuzz
+                    }
+                    None => 0.0,
+                };
+                Ok(Material::Metal {
+                    color: self.color(self.required(&table, "color")?)?,
+                    fuzz,
+                })
+            }
+            "dielectric" => {
+                self.check_keys(&table, &["type", "color", "refraction_index"])?;
+                Ok(Material::Dielectric {
+                    color: match table.get("color") {
+                        Some(value) => self.color(value)?,
+                        None => Vec3::identity(),
+                    },
+                    refraction_index: self.positive(self.required(&table, "refraction_index")?)?,
+                })
+            }
+            "light" => {
+                self.check_keys(&table, &["type", "color", "intensity"])?;
+                self.light_material(&table)
+            }
+            kind => Err(self.error(
+                kind_value.span(),
+                format!(
+                    "unknown material type '{}', expected lambert, metal, dielectric or light",
+                    kind
+                ),
+            )),
+        }
+    }
+
+    fn light_material(&self, table: &Table) -> Result<Material, SceneFileError> {
+        Ok(Material::DiffuseLight {
+            color: match table.get("color") {
+                Some(value) => self.color(value)?,
+                None => Vec3::identity(),
+            },
+            intensity: match table.get("intensity") {
+                Some(value) => self.non_negative(value)?,
+                None => 1.0,
+            },
+        })
+    }
+
+    // the object along with the ids of its materials
+    fn object(&mut self, value: &Value) -> Result<(Geometry, Vec<u32>), SceneFileError> {
+        let table = self.table(value, "object")?;
+        let kind_value = self.required(&table, "type")?;
+        let table = Table {
+            kind: self.string(kind_value)?,
+            ..table
+        };
+
+        let object = match table.kind {
+            "sphere" => {
+                self.check_keys(
+                    &table,
+                    &["type", "center", "radius", "material", "transform"],
+                )?;
+                let (material, material_id) =
+                    self.material_reference(self.required(&table, "material")?)?;
+                let sphere = Geometry::Sphere {
+                    center: self.vec3(self.required(&table, "center")?)?,
+                    radius: self.positive(self.required(&table, "radius")?)?,
+                    material,
+                };
+                (sphere, vec![material_id])
+            }
+            "triangle" => {
+                self.check_keys(
+                    &table,
+                    &[
+                        "type",
+                        "vertices",
+                        "normals",
+                        "uvs",
+                        "material",
+                        "transform",
+                    ],
+                )?;
+                let vertices = self.vertices(self.required(&table, "vertices")?, 3)?;
+                let normals = match table.get("normals") {
+                    Some(value) => {
+                        let normals = self.vertices(value, 3)?;
+                        Some([0, 1, 2].map(|i| normals[i]))
+                    }
+                    None => None,
+                };
+                let uvs = match table.get("uvs") {
+                    Some(value) => {
+                        let uvs = self.floats(value, 6)?;
+                        Some([0, 1, 2].map(|i| (uvs[2 * i], uvs[2 * i + 1])))
+                    }
+                    None => None,
+                };
+                let (material, material_id) =
+                    self.material_reference(self.required(&table, "material")?)?;
+                let triangle = Geometry::Triangle {
+                    vertices: [vertices[0], vertices[1], vertices[2]],
+                    normals,
+                    uvs,
+                    material,
+                };
+                (triangle, vec![material_id])
+            }
+            "mesh" => {
+                self.check_keys(&table, &["type", "path", "mesh", "material", "transform"])?;
+                let (mesh, material_ids) = match table.get("mesh") {
+                    Some(value) => {
+                        if table.get("path").is_some() || table.get("material").is_some() {
+                            return Err(self.error(
+                                value.span(),
+                                String::from(
+                                    "a mesh object has either a mesh or a path and a material",
+                                ),
+                            ));
+                        }
+                        self.mesh_reference(value)?
+                    }
+                    None => self.mesh_file(&table)?,
+                };
+                let object = match table.get("transform") {
+                    Some(_) => self.instance(&table, mesh)?,
+                    // the mesh data is shared by every object using the same mesh
+                    None => Geometry::clone(&mesh),
+                };
+                return Ok((object, material_ids));
+            }
+            kind => {
+                return Err(self.error(
+                    kind_value.span(),
+                    format!(
+                        "unknown object type '{}', expected sphere, triangle or mesh",
+                        kind
+                    ),
+                ))
+            }
+        };
+
+        let (object, material_ids) = object;
+        match table.get("transform") {
+            Some(_) => Ok((self.instance(&table, Arc::new(object))?, material_ids)),
+            None => Ok((object, material_ids)),
+        }
+    }
+
+    fn instance(&self, table: &Table, object: Arc<Geometry>) -> Result<Geometry, SceneFileError> {
+        let mut transform = Transform::identity();
+        if let Some(value) = table.get("transform") {
+            for step in self.array(value, "transform")? {
+                transform = transform.then(&self.transform(step)?);
+            }
+        }
+        Ok(Geometry::Instance { object, transform })
+    }
+
+    // a single translate, scale, rotation or matrix, transforms are lists of them applied in
+    // order
+    fn transform(&self, value: &Value) -> Result<Transform, SceneFileError> {
+        let table = self.table(value, "transform")?;
+        if let Some(matrix_value) = table.get("matrix") {
+            self.check_keys(&table, &["matrix", "inverse"])?;
+            let matrix = self.matrix(matrix_value)?;
+            return match table.get("inverse") {
+                Some(value) => Transform::from_matrices(matrix, self.matrix(value)?)
+                    .map_err(|error| self.error(value.span(), error.to_string())),
+                None => Transform::from_matrix(matrix)
+                    .map_err(|error| self.error(matrix_value.span(), error.to_string())),
+            };
+        }
+        self.check_keys(
+            &table,
+            &[
+                "translate",
+                "scale",
+                "rotate_x",
+                "rotate_y",
+                "rotate_z",
+                "rotate",
+            ],
+        )?;
+        let mut entries = table.entries.iter();
+        let (key, value) = match (entries.next(), entries.next()) {
+            (Some(entry), None) => entry,
+            _ => {
+                return Err(self.error(
+                    value.span(),
+                    String::from("every transform step must have exactly one key"),
+                ))
+            }
+        };
+
+        match key.get_ref().as_ref() {
+            "translate" => Ok(Transform::translate(&self.vec3(value)?)),
+            "scale" => {
+                let factors = match value.get_ref() {
+                    DeValue::Array(_) => self.vec3(value)?,
+                    _ => {
+                        let factor = self.float(value)?;
+                        Vec3::new(factor, factor, factor)
+                    }
+                };
+                if factors.x == 0.0 || factors.y == 0.0 || factors.z == 0.0 {
+                    return Err(
+                        self.error(value.span(), String::from("scale factors must not be zero"))
+                    );
+                }
+                Ok(Transform::scale(&factors))
+            }
+            "rotate_x" => Ok(Transform::rotate_x(self.float(value)?)),
+            "rotate_y" => Ok(Transform::rotate_y(self.float(value)?)),
+            "rotate_z" => Ok(Transform::rotate_z(self.float(value)?)),
+            _ => {
+                let rotation = self.table(value, "rotate")?;
+                self.check_keys(&rotation, &["axis", "degrees"])?;
+                Ok(Transform::rotate(
+                    &self.direction(self.required(&rotation, "axis")?)?,
+                    self.float(self.required(&rotation, "degrees")?)?,
+                ))
+            }
+        }
+    }
+
+    fn mesh_definition(&mut self, value: &Value, name: &str) -> Result<SceneMesh, SceneFileError> {
+        let table = self.table(value, name)?;
+        if table.get("path").is_some() {
+            self.check_keys(&table, &["path", "material"])?;
+            return self.mesh_file(&table);
+        }
+        self.check_keys(
+            &table,
+            &[
+                "positions",
+                "normals",
+                "uvs",
+                "triangles",
+                "materials",
+                "material_ids",
+            ],
+        )?;
+
+        let positions = self.vectors(self.required(&table, "positions")?)?;
+        let normals = match table.get("normals") {
+            Some(value) => {
+                Some(self.vertex_attribute(value, positions.len(), |value| self.vec3(value))?)
+            }
+            None => None,
+        };
+        let uvs = match table.get("uvs") {
+            Some(value) => Some(self.vertex_attribute(value, positions.len(), |value| {
+                let uv = self.floats(value, 2)?;
+                Ok((uv[0], uv[1]))
+            })?),
+            None => None,
+        };
+
+        let triangles_value = self.required(&table, "triangles")?;
+        let triangles = self
+            .array(triangles_value, "triangles")?
+            .iter()
+            .map(|value| {
+                let indices = self.indices(value, positions.len())?;
+                match indices.len() {
+                    3 => Ok([indices[0], indices[1], indices[2]]),
+                    count => Err(self.error(
+                        value.span(),
+                        format!("expected 3 vertex indices, found {}", count),
+                    )),
+                }
+            })
+            .collect::<Result<Vec<[u32; 3]>, SceneFileError>>()?;
+        if triangles.is_empty() {
+            return Err(self.error(
+                triangles_value.span(),
+                String::from("a mesh needs at least one triangle"),
+            ));
+        }
+
+        let materials_value = self.required(&table, "materials")?;
+        let (materials, ids): (Vec<Material>, Vec<u32>) = self
+            .array(materials_value, "materials")?
+            .iter()
+            .map(|value| self.material_reference(value))
+            .collect::<Result<Vec<(Material, u32)>, SceneFileError>>()?
+            .into_iter()
+            .unzip();
+        if materials.is_empty() {
+            return Err(self.error(
+                materials_value.span(),
+                String::from("a mesh needs at least one material"),
+            ));
+        }
+        let material_ids = match table.get("material_ids") {
+            Some(value) => {
+                let material_ids = self.indices(value, materials.len())?;
+                if material_ids.len() != triangles.len() {
+                    return Err(self.error(
+                        value.span(),
+                        format!(
+                            "expected one material id per triangle, found {} for {} triangles",
+                            material_ids.len(),
+                            triangles.len()
+                        ),
+                    ));
+                }
+                Some(material_ids)
+            }
+            None => None,
+        };
+
+        let mesh = Arc::new(Geometry::Mesh {
+            mesh: Arc::new(Mesh::new(
+                positions,
+                normals,
+                uvs,
+                triangles,
+                materials,
+                material_ids,
+            )),
+        });
+        Ok((mesh, ids))
+    }
+
+    fn mesh_file(&mut self, table: &Table) -> Result<SceneMesh, SceneFileError> {
+        let path_value = self.required(table, "path")?;
+        let path = self.path(path_value)?;
+        let material_name = match table.get("material") {
+            Some(value) => Some(self.string(value)?.to_string()),
+            None => None,
+        };
+        let key = (path, material_name);
+        if let Some((mesh, material_ids)) = self.mesh_files.get(&key) {
+            return Ok((Arc::clone(mesh), material_ids.clone()));
+        }
+
+        // used by the faces that don't have a material in the MTL files
+        let (default_material, default_material_id) = match table.get("material") {
+            Some(value) => self.material_reference(value)?,
+            None => (
+                Material::Lambert {
+                    texture: Texture::Solid(Vec3::new(
+                        DEFAULT_MESH_COLOR,
+                        DEFAULT_MESH_COLOR,
+                        DEFAULT_MESH_COLOR,
+                    )),
+                },
+                self.new_material_id(),
+            ),
+        };
+        // OBJ errors already name the file they come from
+        let model = ObjModel::load(&key.0)
+            .map_err(|error| self.error(path_value.span(), error.to_string()))?;
+        self.warnings.extend(model.warnings.iter().cloned());
+        if model.triangle_count() == 0 {
+            return Err(self.error(
+                path_value.span(),
+                format!("'{}' doesn't have any faces", key.0.display()),
+            ));
+        }
+
+        // the materials of the MTL files keep their ids when the file is used with another
+        // default material
+        let mut material_ids = vec![];
+        for (name, _) in model.materials.iter() {
+            let mtl_key = (key.0.clone(), name.clone());
+            let material_id = match self.mtl_material_ids.get(&mtl_key) {
+                Some(&material_id) => material_id,
+                None => {
+                    let material_id = self.new_material_id();
+                    self.mtl_material_ids.insert(mtl_key, material_id);
+                    material_id
+                }
+            };
+            material_ids.push(material_id);
+        }
+        material_ids.push(default_material_id);
+
+        let mesh = Arc::new(Geometry::Mesh {
+            mesh: Arc::new(model.to_mesh(default_material)),
+        });
+        self.mesh_files
+            .insert(key, (Arc::clone(&mesh), material_ids.clone()));
+        Ok((mesh, material_ids))
+    }
+
+    fn light(&self, value: &Value) -> Result<Vec<Geometry>, SceneFileError> {
+        let table = self.table(value, "light")?;
+        let kind_value = self.required(&table, "type")?;
+        let table = Table {
+            kind: self.string(kind_value)?,
+            ..table
+        };
+
+        match table.kind {
+            "sphere" => {
+                self.check_keys(&table, &["type", "center", "radius", "color", "intensity"])?;
+                Ok(vec![Geometry::Sphere {
+                    center: self.vec3(self.required(&table, "center")?)?,
+                    radius: self.positive(self.required(&table, "radius")?)?,
+                    material: self.light_material(&table)?,
+                }])
+            }
+            // parallelogram spanned by two edges starting at a corner
+            "quad" => {
+                self.check_keys(
+                    &table,
+                    &["type", "corner", "edge_u", "edge_v", "color", "intensity"],
+                )?;
+                let corner = self.vec3(self.required(&table, "corner")?)?;
+                let edge_u = self.vec3(self.required(&table, "edge_u")?)?;
+                let edge_v_value = self.required(&table, "edge_v")?;
+                let edge_v = self.vec3(edge_v_value)?;
+                if Vec3::cross(&edge_u, &edge_v).near_zero() {
+                    return Err(self.error(
+                        edge_v_value.span(),
+                        String::from("the edges of a quad must not be parallel"),
+                    ));
+                }
+                let material = self.light_material(&table)?;
+                let corners = [
+                    corner,
+                    corner + edge_u,
+                    corner + edge_u + edge_v,
+                    corner + edge_v,
+                ];
+                Ok([[0, 1, 2], [0, 2, 3]]
+                    .iter()
+                    .map(|indices| Geometry::Triangle {
+                        vertices: indices.map(|i| corners[i]),
+                        normals: None,
+                        uvs: None,
+                        material: material.clone(),
+                    })
+                    .collect())
+            }
+            kind => Err(self.error(
+                kind_value.span(),
+                format!("unknown light type '{}', expected sphere or quad", kind),
+            )),
+        }
+    }
+
+    fn material_reference(&self, value: &Value) -> Result<(Material, u32), SceneFileError> {
+        let name = self.string(value)?;
+        self.materials.get(name).cloned().ok_or_else(|| {
+            self.error(
+                value.span(),
+                format!("material '{}' isn't defined in [materials]", name),
+            )
+        })
+    }
+
+    fn mesh_reference(&self, value: &Value) -> Result<SceneMesh, SceneFileError> {
+        let name = self.string(value)?;
+        self.meshes.get(name).cloned().ok_or_else(|| {
+            self.error(
+                value.span(),
+                format!("mesh '{}' isn't defined in [meshes]", name),
+            )
+        })
+    }
+
+    fn texture_reference(&self, value: &Value) -> Result<Texture, SceneFileError> {
+        let name = self.string(value)?;
+        self.textures.get(name).cloned().ok_or_else(|| {
+            self.error(
+                value.span(),
+                format!("texture '{}' isn't defined in [textures]", name),
+            )
+        })
+    }
+
+    fn load_error(
+        &self,
+        value: &Value,
+        path: &Path,
+        error: &dyn std::error::Error,
+    ) -> SceneFileError {
+        self.error(
+            value.span(),
+            format!("can't load '{}': {}", path.display(), error),
+        )
+    }
+
+    fn check_keys(&self, table: &Table, allowed: &[&str]) -> Result<(), SceneFileError> {
+        for key in table.entries.keys() {
+            if !allowed.contains(&key.get_ref().as_ref()) {
+                return Err(self.error(
+                    key.span(),
+                    format!(
+                        "unknown key '{}' in {}, expected one of {}",
+                        key.get_ref(),
+                        table.kind,
+                        allowed.join(", ")
+                    ),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    fn required<'a, 'i>(
+        &self,
+        table: &Table<'a, 'i>,
+        key: &str,
+    ) -> Result<&'a Value<'i>, SceneFileError> {
+        table.get(key).ok_or_else(|| {
+            self.error(
+                table.span.clone(),
+                format!("missing key '{}' in {}", key, table.kind),
+            )
+        })
+    }
+
+    fn table<'a, 'i>(
+        &self,
+        value: &'a Value<'i>,
+        kind: &'a str,
+    ) -> Result<Table<'a, 'i>, SceneFileError> {
+        match value.get_ref() {
+            DeValue::Table(entries) => Ok(Table {
+                entries,
+                span: value.span(),
+                kind,
+            }),
+            other => Err(self.error(
+                value.span(),
+                format!("expected a table for {}, found {}", kind, other.type_str()),
+            )),
+        }
+    }
+
+    fn array<'a, 'i>(
+        &self,
+        value: &'a Value<'i>,
+        kind: &str,
+    ) -> Result<&'a [Value<'i>], SceneFileError> {
+        match value.get_ref() {
+            DeValue::Array(array) => Ok(array),
+            other => Err(self.error(
+                value.span(),
+                format!("expected an array for {}, found {}", kind, other.type_str()),
+            )),
+        }
+    }
+
+    fn string<'a>(&self, value: &'a Value) -> Result<&'a str, SceneFileError> {
+        match value.get_ref() {
+            DeValue::String(string) => Ok(string),
+            other => Err(self.error(
+                value.span(),
+                format!("expected a string, found {}", other.type_str()),
+            )),
+        }
+    }
+
+    fn boolean(&self, value: &Value) -> Result<bool, SceneFileError> {
+        match value.get_ref() {
+            DeValue::Boolean(boolean) => Ok(*boolean),
+            other => Err(self.error(
+                value.span(),
+                format!("expected a boolean, found {}", other.type_str()),
+            )),
+        }
+    }
+
+    fn path(&self, value: &Value) -> Result<PathBuf, SceneFileError> {
+        let path = Path::new(self.string(value)?);
+        Ok(match self.path.parent() {
+            Some(directory) => directory.join(path),
+            None => path.to_path_buf(),
+        })
+    }
+
+    fn integer<T: TryFrom<i64>>(&self, value: &Value) -> Result<T, SceneFileError> {
+        let integer = match value.get_ref() {
+            DeValue::Integer(integer) => i64::from_str_radix(integer.as_str(), integer.radix())
+                .map_err(|error| self.error(value.span(), error.to_string()))?,
+            other => {
+                return Err(self.error(
+                    value.span(),
+                    format!("expected an integer, found {}", other.type_str()),
+                ))
+            }
+        };
+        T::try_from(integer)
+            .map_err(|_| self.error(value.span(), format!("{} is out of range", integer)))
+    }
+
+    // integers are accepted wherever a float is expected, infinities and NaN nowhere
+    fn float(&self, value: &Value) -> Result<f32, SceneFileError> {
+        let float: f32 = match value.get_ref() {
+            DeValue::Float(float) => float
+                .as_str()
+                .parse()
+                .map_err(|_| self.error(value.span(), format!("'{}' is not a number", float)))?,
+            DeValue::Integer(_) => self.integer::<i64>(value)? as f32,
+            other => {
+                return Err(self.error(
+                    value.span(),
+                    format!("expected a number, found {}", other.type_str()),
+                ))
+            }
+        };
+        match float.is_finite() {
+            true => Ok(float),
+            false => Err(self.error(
+                value.span(),
+                format!("expected a finite number, got {}", float),
+            )),
+        }
+    }
+
+    fn positive(&self, value: &Value) -> Result<f32, SceneFileError> {
+        let float = self.float(value)?;
+        match float > 0.0 {
+            true => Ok(float),
+            false => Err(self.error(
+                value.span(),
+                format!("expected a positive number, got {}", float),
+            )),
+        }
+    }
+
+    fn non_negative(&self, value: &Value) -> Result<f32, SceneFileError> {
+        let float = self.float(value)?;
+        match float >= 0.0 {
+            true => Ok(float),
+            false => Err(self.error(
+                value.span(),
+                format!("expected a number that isn't negative, got {}", float),
+            )),
+        }
+    }
+
+    fn floats(&self, value: &Value, count: usize) -> Result<Vec<f32>, SceneFileError> {
+        let array = self.array(value, "a list of numbers")?;
+        if array.len() != count {
+            return Err(self.error(
+                value.span(),
+                format!("expected {} numbers, found {}", count, array.len()),
+            ));
+        }
+        array.iter().map(|value| self.float(value)).collect()
+    }
+
+    fn vec3(&self, value: &Value) -> Result<Vec3, SceneFileError> {
+        let values = self.floats(value, 3)?;
+        Ok(Vec3::new(values[0], values[1], values[2]))
+    }
+
+    fn vertices(&self, value: &Value, count: usize) -> Result<Vec<Vec3>, SceneFileError> {
+        let array = self.array(value, "a list of vectors")?;
+        if array.len() != count {
+            return Err(self.error(
+                value.span(),
+                format!("expected {} vectors, found {}", count, array.len()),
+            ));
+        }
+        array.iter().map(|value| self.vec3(value)).collect()
+    }
+
+    fn vectors(&self, value: &Value) -> Result<Vec<Vec3>, SceneFileError> {
+        self.array(value, "a list of vectors")?
+            .iter()
+            .map(|value| self.vec3(value))
+            .collect()
+    }
+
+    fn vertex_attribute<T, F>(
+        &self,
+        value: &Value,
+        vertex_count: usize,
+        read: F,
+    ) -> Result<Vec<T>, SceneFileError>
+    where
+        F: Fn(&Value) -> Result<T, SceneFileError>,
+    {
+        let array = self.array(value, "a vertex attribute")?;
+        if array.len() != vertex_count {
+            return Err(self.error(
+                value.span(),
+                format!(
+                    "expected one value per position, found {} for {} positions",
+                    array.len(),
+                    vertex_count
+                ),
+            ));
+        }
+        array.iter().map(read).collect()
+    }
+
+    fn indices(&self, value: &Value, count: usize) -> Result<Vec<u32>, SceneFileError> {
+        self.array(value, "a list of indices")?
+            .iter()
+            .map(|value| {
+                let index: u32 = self.integer(value)?;
+                match (index as usize) < count {
+                    true => Ok(index),
+                    false => Err(self.error(
+                        value.span(),
+                        format!("index {} is out of range, {} defined", index, count),
+                    )),
+                }
+            })
+            .collect()
+    }
+
+    // rows of a 4x4 matrix
+    fn matrix(&self, value: &Value) -> Result<Matrix4, SceneFileError> {
+        let rows = self.array(value, "a matrix")?;
+        if rows.len() != 4 {
+            return Err(self.error(
+                value.span(),
+                format!("expected 4 rows, found {}", rows.len()),
+            ));
+        }
+        let mut m = [[0.0; 4]; 4];
+        for (row, value) in m.iter_mut().zip(rows) {
+            row.copy_from_slice(&self.floats(value, 4)?);
+        }
+        let matrix = Matrix4 { m };
+        match matrix.is_affine() {
+            true => Ok(matrix),
+            false => Err(self.error(
+                rows[3].span(),
+                format!("the bottom row must be [0, 0, 0, 1], found {:?}", m[3]),
+            )),
+        }
+    }
+
+    fn direction(&self, value: &Value) -> Result<Vec3, SceneFileError> {
+        let direction = self.vec3(value)?;
+        match direction.near_zero() {
+            true => Err(self.error(
+                value.span(),
+                String::from("expected a direction, found a zero vector"),
+            )),
+            false => Ok(direction),
+        }
+    }
+
+    fn color(&self, value: &Value) -> Result<Vec3, SceneFileError> {
+        let color = self.vec3(value)?;
+        match color.x >= 0.0 && color.y >= 0.0 && color.z >= 0.0 {
+            true => Ok(color),
+            false => Err(self.error(
+                value.span(),
+                String::from("color components must not be negative"),
+            )),
+        }
+    }
+}
+
+struct Writer<'a> {
+    directory: &'a Path,
+    // textures don't have an identity, equal ones share a definition
+    textures: Definitions<String>,
+    // materials are told apart by their id, so that equal materials keep their own ids
+    materials: Definitions<u32>,
+    // meshes shared by several objects with the same materials are only converted once
+    meshes: Definitions<(*const Mesh, Vec<u32>)>,
+}
+
+impl<'a> Writer<'a> {
+    fn new(directory: &'a Path) -> Writer<'a> {
+        Writer {
+            directory,
+            textures: Definitions::new("texture"),
+            materials: Definitions::new("material"),
+            meshes: Definitions::new("mesh"),
+        }
+    }
+
+    fn settings(&self, settings: &RenderSettings) -> toml::Table {
+        let mut table = toml::Table::new();
+        table.insert(String::from("width"), integer(settings.width));
+        table.insert(String::from("height"), integer(settings.height));
+        table.insert(
+            String::from("samples_per_pixel"),
+            integer(settings.samples_per_pixel),
+        );
+        table.insert(
+            String::from("sampler"),
+            toml::Value::String(settings.sampler.to_string()),
+        );
+        table.insert(
+            String::from("filter"),
+            toml::Value::String(settings.filter.to_string()),
+        );
+        if let Some(filter_radius) = settings.filter_radius {
+            table.insert(String::from("filter_radius"), float(filter_radius));
+        }
+        table.insert(
+            String::from("denoise"),
+            toml::Value::Boolean(settings.denoise),
+        );
+        table.insert(String::from("exposure"), float(settings.exposure));
+        table.insert(
+            String::from("tone_map"),
+            toml::Value::String(settings.tone_map.to_string()),
+        );
+        if let Some(white_point) = settings.white_point {
+            table.insert(String::from("white_point"), float(white_point));
+        }
+        if let Some(noise_threshold) = settings.noise_threshold {
+            table.insert(String::from("noise_threshold"), float(noise_threshold));
+        }
+        table.insert(
+            String::from("min_samples_per_pixel"),
+            integer(settings.min_samples_per_pixel),
+        );
+        table.insert(String::from("max_depth"), integer(settings.max_depth));
+        table.insert(String::from("tile_size"), integer(settings.tile_size));
+        table.insert(
+            String::from("tile_order"),
+            toml::Value::String(settings.tile_order.to_string()),
+        );
+        table.insert(
+            String::from("threads"),
+            integer(settings.num_threads as i64),
+        );
+        table.insert(String::from("t_min"), float(settings.t_min));
+        table.insert(String::from("t_max"), float(settings.t_max));
+        table.insert(
+            String::from("split_strategy"),
+            toml::Value::String(settings.split_strategy.to_string()),
+        );
+        // TOML integers are signed, so the seed is stored with the same bits
+        table.insert(String::from("seed"), integer(settings.seed as i64));
+        table
+    }
+
+    fn camera(&self, camera: &CameraSettings) -> toml::Table {
+        let mut table = toml::Table::new();
+        table.insert(String::from("look_from"), vec3(&camera.look_from));
+        table.insert(String::from("look_at"), vec3(&camera.look_at));
+        table.insert(String::from("up"), vec3(&camera.vup));
+        table.insert(
+            String::from("vertical_fov"),
+            float(camera.vertical_field_of_view),
+        );
+        table.insert(String::from("aperture"), float(camera.aperture));
+        table.insert(String::from("focus_distance"), float(camera.focus_dist));
+        table
+    }
+
+    fn background(&self, background: &Background) -> Result<toml::Table, SceneFileError> {
+        let mut table = toml::Table::new();
+        match background {
+            Background::Color(color) => {
+                table.insert(String::from("type"), string("color"));
+                table.insert(String::from("color"), vec3(color));
+            }
+            Background::Gradient { bottom, top, up } => {
+                table.insert(String::from("type"), string("gradient"));
+                table.insert(String::from("bottom"), vec3(bottom));
+                table.insert(String::from("top"), vec3(top));
+                table.insert(String::from("up"), vec3(up));
+            }
+            Background::Environment(environment) => {
+                let path = environment.path().ok_or_else(|| {
+                    SceneFileError::Export(String::from(
+                        "the environment map wasn't loaded from a file",
+                    ))
+                })?;
+                table.insert(String::from("type"), string("environment"));
+                table.insert(String::from("path"), self.path(path)?);
+                table.insert(String::from("rotation"), float(environment.rotation));
+                table.insert(String::from("intensity"), float(environment.intensity));
+            }
+            Background::Custom(_) => {
+                return Err(SceneFileError::Export(String::from(
+                    "custom backgrounds are computed by code",
+                )))
+            }
+        }
+        Ok(table)
+    }
+
+    fn texture(&mut self, texture: &Texture) -> Result<toml::Value, SceneFileError> {
+        let mut table = toml::Table::new();
+        match texture {
+            Texture::Solid(color) => {
+                table.insert(String::from("type"), string("solid"));
+                table.insert(String::from("color"), vec3(color));
+            }
+            Texture::Checker { even, odd, scale } => {
+                table.insert(String::from("type"), string("checker"));
+                table.insert(String::from("even"), vec3(even));
+                table.insert(String::from("odd"), vec3(odd));
+                table.insert(String::from("scale"), float(*scale));
+            }
+            Texture::Image(image) => {
+                let path = image.path().ok_or_else(|| {
+                    SceneFileError::Export(String::from(
+                        "an image texture wasn't loaded from a file",
+                    ))
+                })?;
+                table.insert(String::from("type"), string("image"));
+                table.insert(String::from("path"), self.path(path)?);
+            }
+        }
+        let text = toml::Value::Table(table.clone()).to_string();
+        Ok(self.textures.name(text, table))
+    }
+
+    fn material(
+        &mut self,
+        material: &Material,
+        material_id: u32,
+    ) -> Result<toml::Value, SceneFileError> {
+        if let Some(name) = self.materials.get(&material_id) {
+            return Ok(name);
+        }
+        let mut table = toml::Table::new();
+        match material {
+            Material::Lambert { texture } => {
+                table.insert(String::from("type"), string("lambert"));
+                match texture {
+                    Texture::Solid(color) => table.insert(String::from("color"), vec3(color)),
+                    texture => table.insert(String::from("texture"), self.texture(texture)?),
+                };
+            }
+            Material::Metal { color, fuzz } => {
+                table.insert(String::from("type"), string("metal"));
+                table.insert(String::from("color"), vec3(color));
+                table.insert(String::from("fuzz"), float(*fuzz));
+            }
+            Material::Dielectric {
+                color,
+                refraction_index,
+            } => {
+                table.insert(String::from("type"), string("dielectric"));
+                table.insert(String::from("color"), vec3(color));
+                table.insert(String::from("refraction_index"), float(*refraction_index));
+            }
+            Material::DiffuseLight { color, intensity } => {
+                table.insert(String::from("type"), string("light"));
+                table.insert(String::from("color"), vec3(color));
+                table.insert(String::from("intensity"), float(*intensity));
+            }
+        }
+        // named after their id, which makes the names follow the order of the definitions
+        Ok(self
+            .materials
+            .name_numbered(material_id, material_id as usize, table))
+    }
+
+    // instances are flattened into the object they place, with the combined transform,
+    // `material_ids` are the ids of the materials of the object
+    fn object(
+        &mut self,
+        object: &Geometry,
+        transform: Option<Transform>,
+        material_ids: &[u32],
+    ) -> Result<toml::Table, SceneFileError> {
+        let mut table = toml::Table::new();
+        match object {
+            Geometry::Sphere {
+                center,
+                radius,
+                material,
+            } => {
+                table.insert(String::from("type"), string("sphere"));
+                table.insert(String::from("center"), vec3(center));
+                table.insert(String::from("radius"), float(*radius));
+                table.insert(
+                    String::from("material"),
+                    self.material(material, material_ids[0])?,
+                );
+            }
+            Geometry::Triangle {
+                vertices,
+                normals,
+                uvs,
+                material,
+            } => {
+                table.insert(String::from("type"), string("triangle"));
+                table.insert(
+                    String::from("vertices"),
+                    toml::Value::Array(vertices.iter().map(vec3).collect()),
+                );
+                if let Some(normals) = normals {
+                    table.insert(
+                        String::from("normals"),
+                        toml::Value::Array(normals.iter().map(vec3).collect()),
+                    );
+                }
+                if let Some(uvs) = uvs {
+                    table.insert(
+                        String::from("uvs"),
+                        toml::Value::Array(
+                            uvs.iter()
+                                .flat_map(|&(u, v)| [float(u), float(v)])
+                                .collect(),
+                        ),
+                    );
+                }
+                table.insert(
+                    String::from("material"),
+                    self.material(material, material_ids[0])?,
+                );
+            }
+            Geometry::Mesh { mesh } => {
+                table.insert(String::from("type"), string("mesh"));
+                match mesh.source() {
+                    // the default material comes after the ones of the MTL files
+                    Some(source) => {
+                        table.insert(String::from("path"), self.path(&source.path)?);
+                        table.insert(
+                            String::from("material"),
+                            self.material(
+                                &source.default_material,
+                                material_ids[material_ids.len() - 1],
+                            )?,
+                        );
+                    }
+                    None => {
+                        table.insert(String::from("mesh"), self.mesh(mesh, material_ids)?);
+                    }
+                }
+            }
+            Geometry::Instance {
+                object,
+                transform: instance_transform,
+            } => {
+                let transform = match transform {
+                    Some(transform) => instance_transform.then(&transform),
+                    None => *instance_transform,
+                };
+                return self.object(object, Some(transform), material_ids);
+            }
+        }
+
+        if let Some(transform) = transform {
+            let mut step = toml::Table::new();
+            step.insert(String::from("matrix"), matrix(transform.matrix()));
+            step.insert(String::from("inverse"), matrix(transform.inverse()));
+            table.insert(
+                String::from("transform"),
+                toml::Value::Array(vec![toml::Value::Table(step)]),
+            );
+        }
+        Ok(table)
+    }
+
+    fn mesh(
+        &mut self,
+        mesh: &Arc<Mesh>,
+        material_ids: &[u32],
+    ) -> Result<toml::Value, SceneFileError> {
+        let key = (Arc::as_ptr(mesh), material_ids.to_vec());
+        if let Some(name) = self.meshes.get(&key) {
+            return Ok(name);
+        }
+
+        let mut table = toml::Table::new();
+        table.insert(
+            String::from("positions"),
+            toml::Value::Array(mesh.positions().iter().map(vec3).collect()),
+        );
+        if let Some(normals) = mesh.normals() {
+            table.insert(
+                String::from("normals"),
+                toml::Value::Array(normals.iter().map(vec3).collect()),
+            );
+        }
+        if let Some(uvs) = mesh.uvs() {
+            table.insert(
+                String::from("uvs"),
+                toml::Value::Array(
+                    uvs.iter()
+                        .map(|&(u, v)| toml::Value::Array(vec![float(u), float(v)]))
+                        .collect(),
+                ),
+            );
+        }
+        table.insert(
+            String::from("triangles"),
+            toml::Value::Array(
+                mesh.triangles()
+                    .iter()
+                    .map(|triangle| {
+                        toml::Value::Array(triangle.iter().map(|&i| integer(i)).collect())
+                    })
+                    .collect(),
+            ),
+        );
+        let materials = mesh
+            .materials()
+            .iter()
+            .zip(material_ids)
+            .map(|(material, &material_id)| self.material(material, material_id))
+            .collect::<Result<Vec<toml::Value>, SceneFileError>>()?;
+        table.insert(String::from("materials"), toml::Value::Array(materials));
+        if let Some(material_ids) = mesh.material_ids() {
+            table.insert(
+                String::from("material_ids"),
+                toml::Value::Array(material_ids.iter().map(|&i| integer(i)).collect()),
+            );
+        }
+
+        Ok(self.meshes.name(key, table))
+    }
+
+    // relative to the scene file when the file is inside its directory, absolute otherwise
+    fn path(&self, path: &Path) -> Result<toml::Value, SceneFileError> {
+        let path = fs::canonicalize(path).map_err(|error| SceneFileError::Io {
+            path: path.to_path_buf(),
+            error,
+        })?;
+        let path = path.strip_prefix(self.directory).unwrap_or(&path);
+        match path.to_str() {
+            Some(path) => Ok(string(path)),
+            None => Err(SceneFileError::Export(format!(
+                "'{}' isn't valid unicode",
+                path.display()
+            ))),
+        }
+    }
+}
+
+// definitions along with their generated names, in the order they were added, definitions with
+// the same key share a name
+struct Definitions<K> {
+    prefix: &'static str,
+    definitions: Vec<(K, toml::Table)>,
+    names: HashMap<K, String>,
+}
+
+impl<K: Clone + Eq + Hash> Definitions<K> {
+    fn new(prefix: &'static str) -> Definitions<K> {
+        Definitions {
+            prefix,
+            definitions: vec![],
+            names: HashMap::new(),
+        }
+    }
+
+    fn get(&self, key: &K) -> Option<toml::Value> {
+        self.names.get(key).map(|name| string(name))
+    }
+
+    // returns the name of the definition with the same key, or adds the definition under a new
+    // name
+    fn name(&mut self, key: K, table: toml::Table) -> toml::Value {
+        let number = self.names.len();
+        self.name_numbered(key, number, table)
+    }
+
+    // same as `name` with the number of the new name given
+    fn name_numbered(&mut self, key: K, number: usize, table: toml::Table) -> toml::Value {
+        if let Some(name) = self.get(&key) {
+            return name;
+        }
+        let name = format!("{}_{}", self.prefix, number);
+        self.names.insert(key.clone(), name.clone());
+        self.definitions.push((key, table));
+        toml::Value::String(name)
+    }
+
+    fn into_table(self) -> toml::Table {
+        let names = self.names;
+        self.definitions
+            .into_iter()
+            .map(|(key, table)| (names[&key].clone(), toml::Value::Table(table)))
+            .collect()
+    }
+}
+
+fn string(value: &str) -> toml::Value {
+    toml::Value::String(value.to_string())
+}
+
+fn integer<T: Into<i64>>(value: T) -> toml::Value {
+    toml::Value::Integer(value.into())
+}
+
+// goes through the shortest decimal representation of the f32, so that the file shows 0.1 rather
+// than 0.10000000149011612 and reads back to the same value
+fn float(value: f32) -> toml::Value {
+    toml::Value::Float(value.to_string().parse().unwrap())
+}
+
+fn vec3(vector: &Vec3) -> toml::Value {
+    toml::Value::Array(vec![float(vector.x), float(vector.y), float(vector.z)])
+}
+
+fn matrix(matrix: &Matrix4) -> toml::Value {
+    toml::Value::Array(
+        matrix
+            .m
+            .iter()
+            .map(|row| toml::Value::Array(row.iter().map(|&value| float(value)).collect()))
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_error(source: &str) -> String {
+        match SceneDescription::parse(source, Path::new("test.toml")) {
+            Ok(_) => panic!("the scene was loaded"),
+            Err(error) => error.to_string(),
+        }
+    }
+
+    #[test]
+    fn rejects_an_inverse_that_doesnt_invert_the_matrix() {
+        let source = "\
+[camera]
+look_from = [0.0, 0.0, 1.0]
+look_at = [0.0, 0.0, 0.0]
+vertical_fov = 40
+
+[materials.white]
+type = \"lambert\"
+color = [0.8, 0.8, 0.8]
+
+[[objects]]
+type = \"sphere\"
+center = [0.0, 0.0, 0.0]
+radius = 1
+material = \"white\"
+
+[[objects.transform]]
+matrix = [[2, 0, 0, 0], [0, 2, 0, 0], [0, 0, 2, 0], [0, 0, 0, 1]]
+inverse = [[2, 0, 0, 0], [0, 2, 0, 0], [0, 0, 2, 0], [0, 0, 0, 1]]
+";
+        assert_eq!(
+            parse_error(source),
+            "test.toml:18: the inverse doesn't invert the matrix"
+        );
+    }
+
+    #[test]
+    fn rejects_a_matrix_that_isnt_affine() {
+        let source = "\
+[camera]
+look_from = [0.0, 0.0, 1.0]
+look_at = [0.0, 0.0, 0.0]
+vertical_fov = 40
+
+[materials.white]
+type = \"lambert\"
+color = [0.8, 0.8, 0.8]
+
+[[objects]]
+type = \"sphere\"
+center = [0.0, 0.0, 0.0]
+radius = 1
+material = \"white\"
+
+[[objects.transform]]
+matrix = [
+    [1, 0, 0, 0],
+    [0, 1, 0, 0],
+    [0, 0, 1, 0],
+    [0, 0, 0.5, 1],
+]
+";
+        assert_eq!(
+            parse_error(source),
+            "test.toml:21: the bottom row must be [0, 0, 0, 1], found [0.0, 0.0, 0.5, 1.0]"
+        );
+    }
+
+    #[test]
+    fn rejects_numbers_that_arent_finite() {
+        for (radius, found) in &[
+            ("inf", "inf"),
+            ("-inf", "-inf"),
+            ("nan", "NaN"),
+            ("1e39", "inf"),
+        ] {
+            let source = format!(
+                "\
+[camera]
+look_from = [0.0, 0.0, 1.0]
+look_at = [0.0, 0.0, 0.0]
+vertical_fov = 40
+
+[materials.white]
+type = \"lambert\"
+color = [0.8, 0.8, 0.8]
+
+[[objects]]
+type = \"sphere\"
+center = [0.0, 0.0, 0.0]
+radius = {}
+material = \"white\"
+",
+                radius
+            );
+            assert_eq!(
+                parse_error(&source),
+                format!("test.toml:13: expected a finite number, got {}", found)
+            );
+        }
+    }
+
+    #[test]
+    fn exported_scenes_load_back_exactly() {
+        let directory = Path::new(env!("CARGO_MANIFEST_DIR")).join("scenes");
+        let mut description = SceneDescription::load(directory.join("example.toml")).unwrap();
+        // values without a short decimal representation
+        let material = Material::Metal {
+            color: Vec3::new(0.123_456_79, 1.0 / 7.0, 1e-30),
+            fuzz: 0.1 + 0.2,
+        };
+        let transform = Transform::rotate(&Vec3::new(1.0, 2.0, 3.0), 37.0)
+            .then(&Transform::scale(&Vec3::new(0.3, 3.0, 1.0 / 3.0)));
+        description.scene.add_object(Geometry::Instance {
+            object: Arc::new(Geometry::Sphere {
+                center: Vec3::new(1.0 / 3.0, -7e-9, 1e7),
+                radius: std::f32::consts::PI,
+                material: material.clone(),
+            }),
+            transform,
+        });
+        description.scene.add_object(Geometry::Mesh {
+            mesh: Arc::new(Mesh::new(
+                vec![
+                    Vec3::new(0.1, 0.2, 0.3),
+                    Vec3::new(2.0 / 3.0, 0.0, -1.0),
+                    Vec3::new(0.0, 1.0 / 9.0, 0.0),
+                ],
+                None,
+                Some(vec![(0.1, 0.7), (1.0 / 3.0, 0.0), (0.0, 1.0)]),
+                vec![[0, 1, 2]],
+                vec![material],
+                None,
+            )),
+        });
+
+        let text = description.to_toml(&directory).unwrap();
+        assert!(text.contains("path = \"cube.obj\""));
+        let loaded = SceneDescription::parse(&text, &directory.join("exported.toml")).unwrap();
+        assert_eq!(loaded.to_toml(&directory).unwrap(), text);
+        match &loaded.scene.objects[loaded.scene.objects.len() - 2] {
+            Geometry::Instance {
+                transform: loaded_transform,
+                ..
+            } => assert_eq!(*loaded_transform, transform),
+            _ => panic!("the instance wasn't loaded back"),
+        }
+    }
+
+    // red and copy have the same parameters but are different materials of the scene
+    const NAMED_MATERIALS: &str = "\
+[camera]
+look_from = [0.0, 0.0, 1.0]
+look_at = [0.0, 0.0, 0.0]
+vertical_fov = 40
+
+[materials.red]
+type = \"lambert\"
+color = [0.8, 0.1, 0.1]
+
+[materials.copy]
+type = \"lambert\"
+color = [0.8, 0.1, 0.1]
+
+[[objects]]
+type = \"sphere\"
+center = [0.0, 0.0, 0.0]
+radius = 1
+material = \"copy\"
+
+[[objects]]
+type = \"sphere\"
+center = [2.0, 0.0, 0.0]
+radius = 1
+material = \"red\"
+
+[[objects]]
+type = \"sphere\"
+center = [4.0, 0.0, 0.0]
+radius = 1
+material = \"copy\"
+
+[[lights]]
+type = \"sphere\"
+center = [0.0, 5.0, 0.0]
+radius = 1
+";
+
+    // ids of the materials hit by the objects of the scene, which have a single material
+    fn material_ids(scene: &Scene) -> Vec<u32> {
+        let ray = crate::ray::Ray {
+            origin: Vec3::zero(),
+            direction: Vec3::new(0.0, 0.0, -1.0),
+        };
+        let hit_record = crate::hit_record::HitRecord::new(
+            1.0,
+            &Vec3::new(0.0, 0.0, -1.0),
+            &Vec3::new(0.0, 0.0, 1.0),
+            &ray,
+        );
+        (0..scene.objects.len())
+            .map(|object| scene.material_id(object, &hit_record))
+            .collect()
+    }
+
+    #[test]
+    fn gives_every_named_material_its_own_id() {
+        let scene = SceneDescription::parse(NAMED_MATERIALS, Path::new("test.toml"))
+            .unwrap()
+            .scene;
+        assert_eq!(material_ids(&scene), [2, 1, 2, 3]);
+    }
+
+    #[test]
+    fn exported_materials_keep_their_ids() {
+        let directory = Path::new(env!("CARGO_MANIFEST_DIR")).join("scenes");
+        let description = SceneDescription::parse(NAMED_MATERIALS, Path::new("test.toml")).unwrap();
+        let text = description.to_toml(&directory).unwrap();
+        let loaded = SceneDescription::parse(&text, &directory.join("exported.toml")).unwrap();
+        assert_eq!(material_ids(&loaded.scene), [2, 1, 2, 3]);
+    }
+}
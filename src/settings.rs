@@ -0,0 +1,227 @@
+use std::fmt;
+
+use crate::{
+    bvh::SplitStrategy, filter::Filter, sampler::SamplerKind, tile::TileOrder, tone_map::ToneMap,
+};
+
+const DEFAULT_WIDTH: u32 = 1200;
+const DEFAULT_HEIGHT: u32 = 800;
+const DEFAULT_SAMPLES_PER_PIXEL: u32 = 100;
+const DEFAULT_MIN_SAMPLES_PER_PIXEL: u32 = 16;
+const DEFAULT_MAX_DEPTH: u8 = 50;
+const DEFAULT_TILE_SIZE: u32 = 16;
+const DEFAULT_NUM_THREADS: usize = 10;
+const DEFAULT_T_MIN: f32 = 0.001;
+const DEFAULT_T_MAX: f32 = f32::MAX;
+const DEFAULT_SEED: u64 = 0;
+/// Largest filter radius in pixels, the film of every tile also covering the pixels its
+/// samples reach.
+pub const MAX_FILTER_RADIUS: f32 = 4.0;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SettingsError {
+    ZeroImageSize {
+        width: u32,
+        height: u32,
+    },
+    ZeroSamplesPerPixel,
+    InvalidMinSamplesPerPixel {
+        min_samples_per_pixel: u32,
+        samples_per_pixel: u32,
+    },
+    InvalidNoiseThreshold(f32),
+    InvalidFilterRadius(f32),
+    InvalidExposure(f32),
+    InvalidWhitePoint(f32),
+    ZeroMaxDepth,
+    ZeroThreads,
+    ZeroTileSize,
+    InvalidRayInterval {
+        t_min: f32,
+        t_max: f32,
+    },
+}
+
+impl fmt::Display for SettingsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SettingsError::ZeroImageSize { width, height } => write!(
+                f,
+                "image size must be at least 1x1, got {}x{}",
+                width, height
+            ),
+            SettingsError::ZeroSamplesPerPixel => {
+                write!(f, "samples per pixel must be at least 1")
+            }
+            SettingsError::InvalidMinSamplesPerPixel {
+                min_samples_per_pixel,
+                samples_per_pixel,
+            } => write!(
+                f,
+                "minimum samples per pixel must be between 1 and the {} samples per pixel, got {}",
+                samples_per_pixel, min_samples_per_pixel
+            ),
+            SettingsError::InvalidNoiseThreshold(noise_threshold) => write!(
+                f,
+                "noise threshold must be greater than 0, got {}",
+                noise_threshold
+            ),
+            SettingsError::InvalidFilterRadius(filter_radius) => write!(
+                f,
+                "filter radius must be greater than 0 and at most {}, got {}",
+                MAX_FILTER_RADIUS, filter_radius
+            ),
+            SettingsError::InvalidExposure(exposure) => {
+                write!(f, "exposure must be a finite number, got {}", exposure)
+            }
+            SettingsError::InvalidWhitePoint(white_point) => {
+                write!(f, "white point must be greater than 0, got {}", white_point)
+            }
+            SettingsError::ZeroMaxDepth => write!(f, "max depth must be at least 1"),
+            SettingsError::ZeroThreads => write!(f, "number of threads must be at least 1"),
+            SettingsError::ZeroTileSize => write!(f, "tile size must be at least 1"),
+            SettingsError::InvalidRayInterval { t_min, t_max } => write!(
+                f,
+                "ray interval must satisfy 0 <= t_min < t_max, got {}..{}",
+                t_min, t_max
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SettingsError {}
+
+/// Everything that controls a single call to `ray_trace`.
+#[derive(Debug, Clone)]
+pub struct RenderSettings {
+    pub width: u32,
+    pub height: u32,
+    /// Number of samples of each pixel, or the most a pixel can get with adaptive sampling.
+    pub samples_per_pixel: u32,
+    /// Picks the numbers behind the positions in the pixels, on the lens and the bounces.
+    pub sampler: SamplerKind,
+    /// Spreads every sample over the pixels around it.
+    pub filter: Filter,
+    /// Radius of the filter in pixels, the default one of the filter when `None`.
+    pub filter_radius: Option<f32>,
+    /// Filters the noise out of the image, guided by the albedo and normal of the first hits.
+    pub denoise: bool,
+    /// Keeps what the camera rays hit first in `Render::aovs`, which are otherwise left as if
+    /// nothing was hit unless `denoise` needs them. It depends on the files to save, so scene
+    /// files don't have it.
+    pub aovs: bool,
+    /// Exposure compensation in stops, every stop doubling the brightness of the image.
+    pub exposure: f32,
+    pub tone_map: ToneMap,
+    /// Radiance mapped to white by the tone maps that have one, their default when `None`.
+    pub white_point: Option<f32>,
+    /// Pixels stop being sampled once the relative standard error of their mean brightness
+    /// falls under this threshold. With `None` every pixel gets `samples_per_pixel` samples.
+    pub noise_threshold: Option<f32>,
+    /// Fewest samples a pixel gets with adaptive sampling before its noise is trusted.
+    pub min_samples_per_pixel: u32,
+    /// Maximum number of bounces of a path.
+    pub max_depth: u8,
+    /// The image is split into jobs of `tile_size` x `tile_size` pixels.
+    pub tile_size: u32,
+    pub tile_order: TileOrder,
+    pub num_threads: usize,
+    /// Intersections closer than `t_min` are ignored to avoid shadow acne.
+    pub t_min: f32,
+    pub t_max: f32,
+    /// Used to build the BVH of scenes that don't have one yet.
+    pub split_strategy: SplitStrategy,
+    /// Renders with the same seed produce the same image, whatever the number of threads.
+    pub seed: u64,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        RenderSettings {
+            width: DEFAULT_WIDTH,
+            height: DEFAULT_HEIGHT,
+            samples_per_pixel: DEFAULT_SAMPLES_PER_PIXEL,
+            sampler: SamplerKind::default(),
+            filter: Filter::default(),
+            filter_radius: None,
+            denoise: false,
+            aovs: false,
+            exposure: 0.0,
+            tone_map: ToneMap::default(),
+            white_point: None,
+            noise_threshold: None,
+            min_samples_per_pixel: DEFAULT_MIN_SAMPLES_PER_PIXEL,
+            max_depth: DEFAULT_MAX_DEPTH,
+            tile_size: DEFAULT_TILE_SIZE,
+            tile_order: TileOrder::default(),
+            num_threads: DEFAULT_NUM_THREADS,
+            t_min: DEFAULT_T_MIN,
+            t_max: DEFAULT_T_MAX,
+            split_strategy: SplitStrategy::default(),
+            seed: DEFAULT_SEED,
+        }
+    }
+}
+
+impl RenderSettings {
+    pub fn aspect_ratio(&self) -> f32 {
+        self.width as f32 / self.height as f32
+    }
+
+    pub fn validate(&self) -> Result<(), SettingsError> {
+        if self.width == 0 || self.height == 0 {
+            return Err(SettingsError::ZeroImageSize {
+                width: self.width,
+                height: self.height,
+            });
+        }
+        if self.samples_per_pixel == 0 {
+            return Err(SettingsError::ZeroSamplesPerPixel);
+        }
+        if let Some(noise_threshold) = self.noise_threshold {
+            if noise_threshold.is_nan() || noise_threshold <= 0.0 {
+                return Err(SettingsError::InvalidNoiseThreshold(noise_threshold));
+            }
+            if self.min_samples_per_pixel == 0
+                || self.min_samples_per_pixel > self.samples_per_pixel
+            {
+                return Err(SettingsError::InvalidMinSamplesPerPixel {
+                    min_samples_per_pixel: self.min_samples_per_pixel,
+                    samples_per_pixel: self.samples_per_pixel,
+                });
+            }
+        }
+        if let Some(filter_radius) = self.filter_radius {
+            // written so that NaN values are rejected as well
+            if !(filter_radius > 0.0 && filter_radius <= MAX_FILTER_RADIUS) {
+                return Err(SettingsError::InvalidFilterRadius(filter_radius));
+            }
+        }
+        if !self.exposure.is_finite() {
+            return Err(SettingsError::InvalidExposure(self.exposure));
+        }
+        if let Some(white_point) = self.white_point {
+            if !white_point.is_finite() || white_point <= 0.0 {
+                return Err(SettingsError::InvalidWhitePoint(white_point));
+            }
+        }
+        if self.max_depth == 0 {
+            return Err(SettingsError::ZeroMaxDepth);
+        }
+        if self.num_threads == 0 {
+            return Err(SettingsError::ZeroThreads);
+        }
+        if self.tile_size == 0 {
+            return Err(SettingsError::ZeroTileSize);
+        }
+        // written so that NaN values are rejected as well
+        if !(self.t_min >= 0.0 && self.t_min < self.t_max) {
+            return Err(SettingsError::InvalidRayInterval {
+                t_min: self.t_min,
+                t_max: self.t_max,
+            });
+        }
+
+        Ok(())
+    }
+}
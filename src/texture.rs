@@ -0,0 +1,139 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use image::ImageError;
+
+use crate::vec_three::Vec3;
+
+/// Color that varies over the surface of an object.
+#[derive(Clone)]
+pub enum Texture {
+    Solid(Vec3),
+    /// 3D checker pattern alternating between `even` and `odd`, with `scale` cells per unit.
+    Checker {
+        even: Vec3,
+        odd: Vec3,
+        scale: f32,
+    },
+    Image(Arc<ImageTexture>),
+}
+
+// image textures are only equal when they are the same image
+impl PartialEq for Texture {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Texture::Solid(color), Texture::Solid(other_color)) => color == other_color,
+            (
+                Texture::Checker { even, odd, scale },
+                Texture::Checker {
+                    even: other_even,
+                    odd: other_odd,
+                    scale: other_scale,
+                },
+            ) => even == other_even && odd == other_odd && scale == other_scale,
+            (Texture::Image(image), Texture::Image(other_image)) => Arc::ptr_eq(image, other_image),
+            _ => false,
+        }
+    }
+}
+
+impl From<Vec3> for Texture {
+    fn from(color: Vec3) -> Self {
+        Texture::Solid(color)
+    }
+}
+
+impl Texture {
+    pub fn value(&self, uv: (f32, f32), point: &Vec3) -> Vec3 {
+        match self {
+            Texture::Solid(color) => *color,
+            Texture::Checker { even, odd, scale } => {
+                let cell = (point.x * scale).floor() as i64
+                    + (point.y * scale).floor() as i64
+                    + (point.z * scale).floor() as i64;
+                match cell.rem_euclid(2) == 0 {
+                    true => *even,
+                    false => *odd,
+                }
+            }
+            Texture::Image(image) => image.color(uv),
+        }
+    }
+}
+
+/// Image looked up with the texture coordinates of the hit point, stored as linear colors.
+pub struct ImageTexture {
+    width: usize,
+    height: usize,
+    pixels: Vec<Vec3>,
+    path: Option<PathBuf>,
+}
+
+impl ImageTexture {
+    /// Create a new ImageTexture from linear colors stored row by row, top row first.
+    ///
+    /// # Panics
+    ///
+    /// The `new` function will panic if the image is empty or if the number of pixels doesn't
+    /// match the size.
+    pub fn new(width: usize, height: usize, pixels: Vec<Vec3>) -> ImageTexture {
+        assert!(width > 0 && height > 0);
+        assert_eq!(pixels.len(), width * height);
+
+        ImageTexture {
+            width,
+            height,
+            pixels,
+            path: None,
+        }
+    }
+
+    /// Loads any 8 bit image supported by the `image` crate, decoding its sRGB values.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<ImageTexture, ImageError> {
+        let image = image::open(&path)?.to_rgb8();
+        let pixels = image
+            .pixels()
+            .map(|pixel| {
+                Vec3::new(
+                    srgb_to_linear(pixel[0]),
+                    srgb_to_linear(pixel[1]),
+                    srgb_to_linear(pixel[2]),
+                )
+            })
+            .collect();
+        let mut texture =
+            ImageTexture::new(image.width() as usize, image.height() as usize, pixels);
+        texture.path = Some(path.as_ref().to_path_buf());
+        Ok(texture)
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// File the image was loaded from, `None` when it was created with `new`.
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Nearest pixel to `uv`, with v growing upwards and the texture repeating outside [0, 1].
+    pub fn color(&self, uv: (f32, f32)) -> Vec3 {
+        let u = uv.0.rem_euclid(1.0);
+        let v = 1.0 - uv.1.rem_euclid(1.0);
+        let x = ((u * self.width as f32) as usize).min(self.width - 1);
+        let y = ((v * self.height as f32) as usize).min(self.height - 1);
+        self.pixels[y * self.width + x]
+    }
+}
+
+fn srgb_to_linear(value: u8) -> f32 {
+    let value = value as f32 / 255.0;
+    match value <= 0.04045 {
+        true => value / 12.92,
+        false => ((value + 0.055) / 1.055).powf(2.4),
+    }
+}
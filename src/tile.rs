@@ -0,0 +1,172 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// Rectangle of pixels rendered by a single job, in image coordinates with the origin at the
+/// top left.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tile {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Order in which the tiles are handed out to the threads.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TileOrder {
+    /// Row by row from the top left.
+    #[default]
+    Scanline,
+    /// Ring by ring from the center of the image outwards.
+    Spiral,
+    /// Along a Hilbert curve, so consecutive tiles are always neighbours.
+    Hilbert,
+}
+
+impl fmt::Display for TileOrder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            TileOrder::Scanline => "scanline",
+            TileOrder::Spiral => "spiral",
+            TileOrder::Hilbert => "hilbert",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for TileOrder {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_lowercase().as_str() {
+            "scanline" => Ok(TileOrder::Scanline),
+            "spiral" => Ok(TileOrder::Spiral),
+            "hilbert" => Ok(TileOrder::Hilbert),
+            _ => Err(format!(
+                "unknown tile order '{}', expected one of scanline, spiral, hilbert",
+                value
+            )),
+        }
+    }
+}
+
+/// Splits a `width` x `height` image into tiles of at most `tile_size` x `tile_size` pixels
+/// that cover every pixel exactly once, the tiles of the last row and column being smaller
+/// when the size isn't a multiple of `tile_size`.
+///
+/// # Panics
+///
+/// The `tiles` function will panic if `tile_size` is zero.
+pub fn tiles(width: u32, height: u32, tile_size: u32, order: TileOrder) -> Vec<Tile> {
+    assert!(tile_size > 0);
+
+    let columns = width.div_ceil(tile_size);
+    let rows = height.div_ceil(tile_size);
+
+    let mut cells: Vec<(u32, u32)> = (0..rows)
+        .flat_map(|row| (0..columns).map(move |column| (column, row)))
+        .collect();
+    match order {
+        TileOrder::Scanline => {}
+        TileOrder::Spiral => {
+            let center_x = (columns as f32 - 1.0) / 2.0;
+            let center_y = (rows as f32 - 1.0) / 2.0;
+            let key = |&(column, row): &(u32, u32)| {
+                let dx = column as f32 - center_x;
+                let dy = row as f32 - center_y;
+                // the ring first, then clockwise from the top inside a ring
+                let ring = dx.abs().max(dy.abs());
+                let angle = dx.atan2(-dy).rem_euclid(std::f32::consts::TAU);
+                (ring, angle)
+            };
+            cells.sort_by(|a, b| key(a).partial_cmp(&key(b)).unwrap());
+        }
+        TileOrder::Hilbert => {
+            let size = columns.max(rows).next_power_of_two();
+            cells.sort_by_key(|&(column, row)| hilbert_index(size, column, row));
+        }
+    }
+
+    cells
+        .into_iter()
+        .map(|(column, row)| {
+            let x = column * tile_size;
+            let y = row * tile_size;
+            Tile {
+                x,
+                y,
+                width: tile_size.min(width - x),
+                height: tile_size.min(height - y),
+            }
+        })
+        .collect()
+}
+
+// distance of (x, y) along the Hilbert curve filling a `size` x `size` grid, `size` being a
+// power of two
+fn hilbert_index(size: u32, mut x: u32, mut y: u32) -> u64 {
+    let mut index = 0;
+    let mut s = size / 2;
+    while s > 0 {
+        let rx = (x & s > 0) as u32;
+        let ry = (y & s > 0) as u32;
+        index += s as u64 * s as u64 * ((3 * rx) ^ ry) as u64;
+        // rotate the quadrant so that the curve inside it starts and ends at the right corners
+        if ry == 0 {
+            if rx == 1 {
+                x = size - 1 - x;
+                y = size - 1 - y;
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+        s /= 2;
+    }
+    index
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn covers_every_pixel_once() {
+        for (width, height) in [(1, 1), (7, 3), (33, 64), (100, 17)] {
+            for tile_size in [1, 5, 16, 64, 101] {
+                for order in [TileOrder::Scanline, TileOrder::Spiral, TileOrder::Hilbert] {
+                    let mut covered = vec![0; width as usize * height as usize];
+                    for tile in tiles(width, height, tile_size, order) {
+                        assert!(tile.width > 0 && tile.height > 0);
+                        assert!(tile.width <= tile_size && tile.height <= tile_size);
+                        assert!(tile.x + tile.width <= width && tile.y + tile.height <= height);
+                        for y in tile.y..tile.y + tile.height {
+                            for x in tile.x..tile.x + tile.width {
+                                covered[(y * width + x) as usize] += 1;
+                            }
+                        }
+                    }
+                    assert!(
+                        covered.iter().all(|&count| count == 1),
+                        "{}x{} image, {} pixel tiles in {} order",
+                        width,
+                        height,
+                        tile_size,
+                        order
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn follows_the_hilbert_curve_through_neighbours() {
+        // on a power of two grid the curve never leaves the image
+        let tiles = tiles(64, 64, 8, TileOrder::Hilbert);
+        assert_eq!(tiles.len(), 64);
+        for pair in tiles.windows(2) {
+            assert_eq!(
+                pair[0].x.abs_diff(pair[1].x) + pair[0].y.abs_diff(pair[1].y),
+                8
+            );
+        }
+    }
+}
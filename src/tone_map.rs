@@ -0,0 +1,163 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::vec_three::Vec3;
+
+/// Curve bringing the linear radiance of a render into the [0, 1] range of a display.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ToneMap {
+    /// No curve, everything brighter than 1 is clipped.
+    #[default]
+    Clamp,
+    /// `L / (1 + L)` on the luminance, which never quite reaches white.
+    Reinhard,
+    /// Reinhard reaching white at the white point.
+    ExtendedReinhard,
+    /// John Hable's filmic curve from Uncharted 2, white at the white point.
+    Hable,
+    /// Krzysztof Narkowicz's fit of the ACES filmic curve.
+    Aces,
+}
+
+impl ToneMap {
+    /// Linear radiance mapped to white when the settings don't give a white point, only used
+    /// by `ExtendedReinhard` and `Hable`.
+    pub fn default_white_point(&self) -> f32 {
+        match self {
+            ToneMap::Hable => 11.2,
+            _ => 4.0,
+        }
+    }
+
+    /// Maps `color`, already scaled by the exposure, to [0, 1].
+    pub fn apply(&self, color: Vec3, white_point: f32) -> Vec3 {
+        match self {
+            ToneMap::Clamp => color,
+            ToneMap::Reinhard => scale_luminance(color, |luminance| luminance / (1.0 + luminance)),
+            ToneMap::ExtendedReinhard => {
+                // the curve is already close to 1 at the white point, dividing by its value
+                // there makes it exact
+                let white_scale = 1.0 / extended_reinhard(white_point, white_point);
+                scale_luminance(color, |luminance| {
+                    extended_reinhard(luminance, white_point) * white_scale
+                })
+            }
+            ToneMap::Hable => {
+                // the curve expects the exposure to be doubled, the white point as well
+                let white_scale = 1.0 / hable(2.0 * white_point);
+                map_channels(color, |value| hable(2.0 * value) * white_scale)
+            }
+            ToneMap::Aces => map_channels(color, |value| {
+                (value * (2.51 * value + 0.03)) / (value * (2.43 * value + 0.59) + 0.14)
+            }),
+        }
+    }
+}
+
+impl fmt::Display for ToneMap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            ToneMap::Clamp => "clamp",
+            ToneMap::Reinhard => "reinhard",
+            ToneMap::ExtendedReinhard => "extended-reinhard",
+            ToneMap::Hable => "hable",
+            ToneMap::Aces => "aces",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for ToneMap {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_lowercase().as_str() {
+            "clamp" => Ok(ToneMap::Clamp),
+            "reinhard" => Ok(ToneMap::Reinhard),
+            "extended-reinhard" => Ok(ToneMap::ExtendedReinhard),
+            "hable" => Ok(ToneMap::Hable),
+            "aces" => Ok(ToneMap::Aces),
+            _ => Err(format!(
+                "unknown tone map '{}', expected one of clamp, reinhard, extended-reinhard, hable, aces",
+                value
+            )),
+        }
+    }
+}
+
+/// Encodes a linear value in [0, 1] with the sRGB transfer function.
+pub fn linear_to_srgb(value: f32) -> f32 {
+    match value <= 0.003_130_8 {
+        true => 12.92 * value,
+        false => 1.055 * value.powf(1.0 / 2.4) - 0.055,
+    }
+}
+
+// scales the color so that its luminance follows `curve`, keeping its hue
+fn scale_luminance<F: Fn(f32) -> f32>(color: Vec3, curve: F) -> Vec3 {
+    let luminance = color.luminance();
+    match luminance > 0.0 {
+        true => color * (curve(luminance) / luminance),
+        false => Vec3::zero(),
+    }
+}
+
+fn map_channels<F: Fn(f32) -> f32>(color: Vec3, curve: F) -> Vec3 {
+    Vec3::new(curve(color.x), curve(color.y), curve(color.z))
+}
+
+fn extended_reinhard(luminance: f32, white_point: f32) -> f32 {
+    luminance * (1.0 + luminance / (white_point * white_point)) / (1.0 + luminance)
+}
+
+fn hable(value: f32) -> f32 {
+    let (a, b, c, d, e, f) = (0.15, 0.50, 0.10, 0.20, 0.02, 0.30);
+    ((value * (a * value + c * b) + d * e) / (value * (a * value + b) + d * f)) - e / f
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WHITE_POINT_CURVES: [ToneMap; 2] = [ToneMap::ExtendedReinhard, ToneMap::Hable];
+
+    #[test]
+    fn maps_the_white_point_to_white() {
+        for tone_map in WHITE_POINT_CURVES {
+            for white_point in [0.5, 1.0, 4.0, tone_map.default_white_point(), 100.0] {
+                let gray = Vec3::new(white_point, white_point, white_point);
+                let mapped = tone_map.apply(gray, white_point);
+                assert!(
+                    (mapped - Vec3::new(1.0, 1.0, 1.0)).length() < 1e-5,
+                    "{} maps the white point {} to {:?}",
+                    tone_map,
+                    white_point,
+                    mapped
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn brighter_values_map_to_brighter_ones() {
+        for tone_map in WHITE_POINT_CURVES {
+            let white_point = tone_map.default_white_point();
+            let mut previous = 0.0;
+            for step in 1..=1000 {
+                let value = step as f32 * 2.0 * white_point / 1000.0;
+                let mapped = tone_map
+                    .apply(Vec3::new(value, value, value), white_point)
+                    .x;
+                assert!(
+                    mapped > previous,
+                    "{} maps {} to {}, below {}",
+                    tone_map,
+                    value,
+                    mapped,
+                    previous
+                );
+                previous = mapped;
+            }
+        }
+    }
+}
@@ -0,0 +1,341 @@
+use std::fmt;
+use std::ops;
+
+use crate::{aabb::AABB, ray::Ray, vec_three::Vec3};
+
+// how far from the identity the product of a matrix and the inverse given along with it can be,
+// relative to the size of the terms it sums
+const INVERSE_TOLERANCE: f32 = 1e-4;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Matrix4 {
+    pub m: [[f32; 4]; 4],
+}
+
+impl ops::Mul<Matrix4> for Matrix4 {
+    type Output = Matrix4;
+
+    fn mul(self, other: Matrix4) -> Self::Output {
+        let mut m = [[0.0; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = (0..4).map(|k| self.m[i][k] * other.m[k][j]).sum();
+            }
+        }
+        Matrix4 { m }
+    }
+}
+
+impl Matrix4 {
+    pub fn identity() -> Matrix4 {
+        Matrix4 {
+            m: [
+                [1.0, 0.0, 0.0, 0.0],
+                [0.0, 1.0, 0.0, 0.0],
+                [0.0, 0.0, 1.0, 0.0],
+                [0.0, 0.0, 0.0, 1.0],
+            ],
+        }
+    }
+
+    pub fn transpose(&self) -> Matrix4 {
+        let mut m = [[0.0; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = self.m[j][i];
+            }
+        }
+        Matrix4 { m }
+    }
+
+    /// Whether the bottom row is 0, 0, 0, 1, so that the matrix doesn't need a projective
+    /// divide.
+    pub fn is_affine(&self) -> bool {
+        self.m[3] == [0.0, 0.0, 0.0, 1.0]
+    }
+
+    /// Inverts the matrix with Gauss-Jordan elimination, `None` if it is singular.
+    pub fn inverse(&self) -> Option<Matrix4> {
+        // pivots are compared to the largest entry, so that tiny scales can be inverted while
+        // large matrices with nearly dependent rows can't
+        let magnitude = self
+            .m
+            .iter()
+            .flatten()
+            .fold(0.0f32, |magnitude, value| magnitude.max(value.abs()));
+        let mut m = self.m;
+        let mut inverse = Matrix4::identity().m;
+        for column in 0..4 {
+            // partial pivoting keeps the elimination stable
+            let pivot = (column..4)
+                .max_by(|&a, &b| m[a][column].abs().total_cmp(&m[b][column].abs()))
+                .unwrap();
+            if m[pivot][column].abs() <= f32::EPSILON * magnitude {
+                return None;
+            }
+            m.swap(column, pivot);
+            inverse.swap(column, pivot);
+
+            let scale = 1.0 / m[column][column];
+            for j in 0..4 {
+                m[column][j] *= scale;
+                inverse[column][j] *= scale;
+            }
+            for row in 0..4 {
+                if row == column {
+                    continue;
+                }
+                let factor = m[row][column];
+                for j in 0..4 {
+                    m[row][j] -= factor * m[column][j];
+                    inverse[row][j] -= factor * inverse[column][j];
+                }
+            }
+        }
+        Some(Matrix4 { m: inverse })
+    }
+
+    pub fn transform_point(&self, point: &Vec3) -> Vec3 {
+        let m = &self.m;
+        let x = m[0][0] * point.x + m[0][1] * point.y + m[0][2] * point.z + m[0][3];
+        let y = m[1][0] * point.x + m[1][1] * point.y + m[1][2] * point.z + m[1][3];
+        let z = m[2][0] * point.x + m[2][1] * point.y + m[2][2] * point.z + m[2][3];
+        let w = m[3][0] * point.x + m[3][1] * point.y + m[3][2] * point.z + m[3][3];
+        match w == 1.0 {
+            true => Vec3::new(x, y, z),
+            false => Vec3::new(x, y, z) / w,
+        }
+    }
+
+    pub fn transform_vector(&self, vector: &Vec3) -> Vec3 {
+        let m = &self.m;
+        Vec3::new(
+            m[0][0] * vector.x + m[0][1] * vector.y + m[0][2] * vector.z,
+            m[1][0] * vector.x + m[1][1] * vector.y + m[1][2] * vector.z,
+            m[2][0] * vector.x + m[2][1] * vector.y + m[2][2] * vector.z,
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransformError {
+    /// The bottom row of the matrix isn't 0, 0, 0, 1.
+    NotAffine,
+    Singular,
+    /// The inverse given along with the matrix doesn't invert it.
+    WrongInverse,
+}
+
+impl fmt::Display for TransformError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TransformError::NotAffine => {
+                write!(f, "the bottom row of the matrix must be [0, 0, 0, 1]")
+            }
+            TransformError::Singular => write!(f, "the matrix can't be inverted"),
+            TransformError::WrongInverse => write!(f, "the inverse doesn't invert the matrix"),
+        }
+    }
+}
+
+impl std::error::Error for TransformError {}
+
+/// Affine transform along with its inverse and the matrix its normals are transformed by.
+///
+/// Translations, rotations and scales, and the transforms combined from them, build their
+/// inverse from the known inverses of the steps; only `from_matrix` has to invert a matrix.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transform {
+    matrix: Matrix4,
+    inverse: Matrix4,
+    // the inverse transpose, which keeps normals perpendicular to the surface under non uniform
+    // scaling
+    normal_matrix: Matrix4,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Transform::identity()
+    }
+}
+
+impl Transform {
+    fn new(matrix: Matrix4, inverse: Matrix4) -> Transform {
+        Transform {
+            matrix,
+            inverse,
+            normal_matrix: inverse.transpose(),
+        }
+    }
+
+    pub fn identity() -> Transform {
+        Transform::new(Matrix4::identity(), Matrix4::identity())
+    }
+
+    /// Transform of an affine matrix.
+    pub fn from_matrix(matrix: Matrix4) -> Result<Transform, TransformError> {
+        if !matrix.is_affine() {
+            return Err(TransformError::NotAffine);
+        }
+        matrix
+            .inverse()
+            .map(|inverse| Transform::new(matrix, inverse))
+            .ok_or(TransformError::Singular)
+    }
+
+    /// Transform of an affine matrix whose inverse is already known, which keeps it exact. The
+    /// product of the two matrices has to be the identity, up to rounding.
+    pub fn from_matrices(matrix: Matrix4, inverse: Matrix4) -> Result<Transform, TransformError> {
+        if !matrix.is_affine() || !inverse.is_affine() {
+            return Err(TransformError::NotAffine);
+        }
+        let product = matrix * inverse;
+        for (i, row) in product.m.iter().enumerate() {
+            for (j, value) in row.iter().enumerate() {
+                let expected = if i == j { 1.0 } else { 0.0 };
+                // the rounding of every term of the sum adds up
+                let magnitude: f32 = (0..4)
+                    .map(|k| (matrix.m[i][k] * inverse.m[k][j]).abs())
+                    .sum();
+                if (value - expected).abs() > INVERSE_TOLERANCE * magnitude.max(1.0) {
+                    return Err(TransformError::WrongInverse);
+                }
+            }
+        }
+        Ok(Transform::new(matrix, inverse))
+    }
+
+    pub fn matrix(&self) -> &Matrix4 {
+        &self.matrix
+    }
+
+    pub fn inverse(&self) -> &Matrix4 {
+        &self.inverse
+    }
+
+    pub fn translate(offset: &Vec3) -> Transform {
+        let mut matrix = Matrix4::identity();
+        let mut inverse = Matrix4::identity();
+        for i in 0..3 {
+            matrix.m[i][3] = offset.get(i as u8);
+            inverse.m[i][3] = -offset.get(i as u8);
+        }
+        Transform::new(matrix, inverse)
+    }
+
+    /// # Panics
+    ///
+    /// The `scale` function will panic if any of the factors is zero.
+    pub fn scale(factors: &Vec3) -> Transform {
+        assert!(factors.x != 0.0 && factors.y != 0.0 && factors.z != 0.0);
+
+        let mut matrix = Matrix4::identity();
+        let mut inverse = Matrix4::identity();
+        for i in 0..3 {
+            matrix.m[i][i] = factors.get(i as u8);
+            inverse.m[i][i] = 1.0 / factors.get(i as u8);
+        }
+        Transform::new(matrix, inverse)
+    }
+
+    /// Rotation by `degrees` around `axis`, counter clockwise when looking down the axis.
+    pub fn rotate(axis: &Vec3, degrees: f32) -> Transform {
+        let a = axis.unit_vector();
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        let matrix = Matrix4 {
+            m: [
+                [
+                    cos + a.x * a.x * (1.0 - cos),
+                    a.x * a.y * (1.0 - cos) - a.z * sin,
+                    a.x * a.z * (1.0 - cos) + a.y * sin,
+                    0.0,
+                ],
+                [
+                    a.y * a.x * (1.0 - cos) + a.z * sin,
+                    cos + a.y * a.y * (1.0 - cos),
+                    a.y * a.z * (1.0 - cos) - a.x * sin,
+                    0.0,
+                ],
+                [
+                    a.z * a.x * (1.0 - cos) - a.y * sin,
+                    a.z * a.y * (1.0 - cos) + a.x * sin,
+                    cos + a.z * a.z * (1.0 - cos),
+                    0.0,
+                ],
+                [0.0, 0.0, 0.0, 1.0],
+            ],
+        };
+        // the inverse of a rotation is its transpose
+        Transform::new(matrix, matrix.transpose())
+    }
+
+    pub fn rotate_x(degrees: f32) -> Transform {
+        Transform::rotate(&Vec3::new(1.0, 0.0, 0.0), degrees)
+    }
+
+    pub fn rotate_y(degrees: f32) -> Transform {
+        Transform::rotate(&Vec3::new(0.0, 1.0, 0.0), degrees)
+    }
+
+    pub fn rotate_z(degrees: f32) -> Transform {
+        Transform::rotate(&Vec3::new(0.0, 0.0, 1.0), degrees)
+    }
+
+    /// Returns the transform that applies `self` first and `next` afterwards.
+    pub fn then(&self, next: &Transform) -> Transform {
+        Transform::new(next.matrix * self.matrix, self.inverse * next.inverse)
+    }
+
+    pub fn inverted(&self) -> Transform {
+        Transform::new(self.inverse, self.matrix)
+    }
+
+    pub fn transform_point(&self, point: &Vec3) -> Vec3 {
+        self.matrix.transform_point(point)
+    }
+
+    pub fn transform_vector(&self, vector: &Vec3) -> Vec3 {
+        self.matrix.transform_vector(vector)
+    }
+
+    /// Normals are transformed by the inverse transpose so they stay perpendicular to the
+    /// surface under non uniform scaling. The result isn't normalized.
+    pub fn transform_normal(&self, normal: &Vec3) -> Vec3 {
+        self.normal_matrix.transform_vector(normal)
+    }
+
+    /// Moves a world space ray into the space the transform was applied to. The direction
+    /// isn't normalized, so distances along the ray stay the same in both spaces.
+    pub fn inverse_transform_ray(&self, ray: &Ray) -> Ray {
+        Ray {
+            origin: self.inverse.transform_point(&ray.origin),
+            direction: self.inverse.transform_vector(&ray.direction),
+        }
+    }
+
+    pub fn transform_box(&self, bounding_box: &AABB) -> AABB {
+        let mut transformed: Option<AABB> = None;
+        for corner in 0..8 {
+            let point = Vec3::new(
+                match corner & 1 {
+                    0 => bounding_box.minimum.x,
+                    _ => bounding_box.maximum.x,
+                },
+                match corner & 2 {
+                    0 => bounding_box.minimum.y,
+                    _ => bounding_box.maximum.y,
+                },
+                match corner & 4 {
+                    0 => bounding_box.minimum.z,
+                    _ => bounding_box.maximum.z,
+                },
+            );
+            let point_box = AABB::from_point(&self.transform_point(&point));
+            transformed = Some(match transformed {
+                Some(transformed) => AABB::surrounding_box(&transformed, &point_box),
+                None => point_box,
+            });
+        }
+        transformed.unwrap()
+    }
+}
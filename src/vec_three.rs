@@ -1,11 +1,12 @@
 extern crate rand;
 
 use rand::Rng;
+use std::f32::consts::PI;
 use std::ops;
 
 const S_MIN: f32 = 1e-8;
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub struct Vec3 {
     pub x: f32,
     pub y: f32,
@@ -151,6 +152,11 @@ impl Vec3 {
         unit_v / length
     }
 
+    /// Perceived brightness of a linear Rec. 709 color.
+    pub fn luminance(&self) -> f32 {
+        0.2126 * self.x + 0.7152 * self.y + 0.0722 * self.z
+    }
+
     pub fn get(&self, i: u8) -> f32 {
         match i {
             0 => self.x,
@@ -172,8 +178,7 @@ impl Vec3 {
         }
     }
 
-    pub fn random_vec3(min: f32, max: f32) -> Vec3 {
-        let mut rng = rand::thread_rng();
+    pub fn random_vec3<R: Rng + ?Sized>(rng: &mut R, min: f32, max: f32) -> Vec3 {
         Vec3::new(
             rng.gen_range(min..=max),
             rng.gen_range(min..=max),
@@ -181,33 +186,26 @@ impl Vec3 {
         )
     }
 
-    pub fn random_in_unit_sphere() -> Vec3 {
-        let mut point: Vec3;
-        loop {
-            point = Vec3::random_vec3(-1.0, 1.0);
-            if point.length_squared() < 1.0 {
-                break;
-            }
-        }
-        point
+    /// Point uniformly distributed in the unit sphere for a uniform `sample` in [0, 1)² and
+    /// `radius_sample` in [0, 1).
+    pub fn sample_in_unit_sphere(sample: (f32, f32), radius_sample: f32) -> Vec3 {
+        Vec3::sample_unit_vector(sample) * radius_sample.cbrt()
     }
 
-    pub fn random_unit_vector() -> Vec3 {
-        Vec3::random_in_unit_sphere().unit_vector()
+    /// Direction uniformly distributed on the unit sphere for a uniform `sample` in [0, 1)².
+    pub fn sample_unit_vector(sample: (f32, f32)) -> Vec3 {
+        let z = 1.0 - 2.0 * sample.0;
+        let radius = (1.0 - z * z).max(0.0).sqrt();
+        let phi = 2.0 * PI * sample.1;
+        Vec3::new(radius * phi.cos(), radius * phi.sin(), z)
     }
 
-    pub fn random_in_unit_disk() -> Vec3 {
-        let mut rng = rand::thread_rng();
-        loop {
-            let p = Vec3 {
-                x: rng.gen_range(-1.0..=1.0),
-                y: rng.gen_range(-1.0..=1.0),
-                z: 0.0,
-            };
-            if p.length_squared() < 1.0 {
-                return p;
-            }
-        }
+    /// Point uniformly distributed in the unit disk of the xy plane for a uniform `sample` in
+    /// [0, 1)².
+    pub fn sample_in_unit_disk(sample: (f32, f32)) -> Vec3 {
+        let radius = sample.0.sqrt();
+        let phi = 2.0 * PI * sample.1;
+        Vec3::new(radius * phi.cos(), radius * phi.sin(), 0.0)
     }
 
     pub fn near_zero(&self) -> bool {
@@ -221,7 +219,7 @@ impl Vec3 {
     pub fn refract(unit_vector: &Vec3, normal: &Vec3, refraction_ratio: f32) -> Vec3 {
         let uv = *unit_vector;
         let n = *normal;
-        let cos_theta = (Vec3::dot(unit_vector, normal) * -1.0).min(1.0);
+        let cos_theta = (-Vec3::dot(unit_vector, normal)).min(1.0);
         let r_perp = (uv + n * cos_theta) * refraction_ratio;
         let r_parallel = n * (-(1.0 - r_perp.length_squared()).abs().sqrt());
         r_perp + r_parallel